@@ -0,0 +1,353 @@
+use crate::config::AuditConfig;
+use crate::discord::post_message;
+use astrolabe::{DateTime, Precision};
+use log::warn;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::{Arc, RwLock};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::Mutex};
+use twilight_http::Client;
+use twilight_model::{
+    application::interaction::application_command::{
+        CommandData, CommandDataOption, CommandOptionValue,
+    },
+    user::User,
+};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Outcome {
+    Success,
+    /// The user doesn't have the permission level the command requires.
+    Denied,
+    /// The command sent to the server matched a deny pattern.
+    Blocked,
+    NotRunning,
+    AlreadyRunning,
+    Failed,
+}
+
+impl Outcome {
+    fn describe(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Denied => "denied",
+            Outcome::Blocked => "blocked",
+            Outcome::NotRunning => "not running",
+            Outcome::AlreadyRunning => "already running",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp: String,
+    user_id: u64,
+    user: &'a str,
+    command: &'a str,
+    arguments: Map<String, Value>,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Records who ran which command into an append only JSON lines file and, if configured, an audit channel.
+pub(crate) struct AuditLog {
+    config: RwLock<AuditConfig>,
+    client: Arc<Client>,
+    file_lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub(crate) fn new(config: AuditConfig, client: Arc<Client>) -> Self {
+        Self {
+            config: RwLock::new(config),
+            client,
+            file_lock: Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn reconfigure(&self, config: AuditConfig) {
+        *self.config.write().unwrap_or_else(|err| err.into_inner()) = config;
+    }
+
+    /// Records a slash command.
+    pub(crate) async fn record(
+        &self,
+        user: &User,
+        data: &CommandData,
+        outcome: Outcome,
+        detail: Option<String>,
+    ) {
+        self.write(
+            user,
+            &format!("/{}", data.name),
+            options_to_json(&data.options),
+            outcome,
+            detail,
+        )
+        .await;
+    }
+
+    /// Records a button which confirms a command, like the one of `/restore`.
+    pub(crate) async fn record_confirmation(
+        &self,
+        user: &User,
+        command: &str,
+        arguments: &[(&str, &str)],
+        outcome: Outcome,
+        detail: Option<String>,
+    ) {
+        let arguments = arguments
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value.to_string())))
+            .collect();
+        self.write(
+            user,
+            &format!("/{command} (confirmed)"),
+            arguments,
+            outcome,
+            detail,
+        )
+        .await;
+    }
+
+    /// Records a command typed into the console channel.
+    pub(crate) async fn record_console(
+        &self,
+        user: &User,
+        cmd: &str,
+        outcome: Outcome,
+        detail: Option<String>,
+    ) {
+        let mut arguments = Map::new();
+        arguments.insert("command".to_string(), Value::String(cmd.to_string()));
+        self.write(user, "console", arguments, outcome, detail)
+            .await;
+    }
+
+    async fn write(
+        &self,
+        user: &User,
+        command: &str,
+        arguments: Map<String, Value>,
+        outcome: Outcome,
+        detail: Option<String>,
+    ) {
+        let entry = Entry {
+            timestamp: DateTime::now().format_rfc3339(Precision::Seconds),
+            user_id: user.id.get(),
+            user: &user.name,
+            command,
+            arguments,
+            outcome,
+            detail,
+        };
+
+        let AuditConfig { path, channel_id } = self
+            .config
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone();
+
+        let mut line = serde_json::to_string(&entry).expect("Failed serializing audit entry");
+        line.push('\n');
+        {
+            let _lock = self.file_lock.lock().await;
+            let result = async {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await?;
+                file.write_all(line.as_bytes()).await?;
+                // Tokio writes in the background, without the flush the next entry could overtake this one
+                file.flush().await
+            }
+            .await;
+            if let Err(err) = result {
+                warn!("Failed writing to audit log {}: {err}", path.display());
+            }
+        }
+
+        if let Some(channel_id) = channel_id {
+            let arguments = entry
+                .arguments
+                .iter()
+                .map(|(name, value)| match value {
+                    Value::String(value) => format!(" {name}: {value}"),
+                    value => format!(" {name}: {value}"),
+                })
+                .collect::<String>();
+            let mut msg = format!(
+                ":scroll: <@{}> `{}{}` → {}",
+                entry.user_id,
+                entry.command,
+                arguments.replace('`', "'"),
+                entry.outcome.describe()
+            );
+            if let Some(detail) = &entry.detail {
+                msg.push_str(&format!(" ({detail})"));
+            }
+            post_message(&self.client, &msg, channel_id)
+                .await
+                .unwrap_or_else(|err| warn!("Failed to send message to audit channel: {err}"));
+        }
+    }
+}
+
+fn options_to_json(options: &[CommandDataOption]) -> Map<String, Value> {
+    options
+        .iter()
+        .map(|option| {
+            let value = match &option.value {
+                CommandOptionValue::String(value) => Value::String(value.clone()),
+                CommandOptionValue::Integer(value) => Value::from(*value),
+                CommandOptionValue::Number(value) => Value::from(*value),
+                CommandOptionValue::Boolean(value) => Value::Bool(*value),
+                CommandOptionValue::SubCommand(options)
+                | CommandOptionValue::SubCommandGroup(options) => {
+                    Value::Object(options_to_json(options))
+                }
+                value => Value::String(format!("{value:?}")),
+            };
+            (option.name.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn user() -> User {
+        serde_json::from_value(serde_json::json!({
+            "id": "42",
+            "username": "steve",
+            "discriminator": "0000",
+            "avatar": null,
+        }))
+        .unwrap()
+    }
+
+    /// An audit log writing into a fresh file, without an audit channel.
+    fn audit_log(test: &str) -> (AuditLog, PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("eve-audit-{test}-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = AuditConfig {
+            path: path.clone(),
+            channel_id: None,
+        };
+        (
+            AuditLog::new(config, Arc::new(Client::new(String::new()))),
+            path,
+        )
+    }
+
+    fn read_entries(path: &PathBuf) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn appends_entries() {
+        let (audit, path) = audit_log("appends");
+
+        audit
+            .record_console(&user(), "op steve", Outcome::Blocked, None)
+            .await;
+        audit
+            .record_confirmation(
+                &user(),
+                "restore",
+                &[("server", "survival"), ("backup", "backup.tar.gz")],
+                Outcome::Failed,
+                Some("the server didn't stop".to_string()),
+            )
+            .await;
+
+        let entries = read_entries(&path);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["user_id"], 42);
+        assert_eq!(entries[0]["user"], "steve");
+        assert_eq!(entries[0]["command"], "console");
+        assert_eq!(
+            entries[0]["arguments"],
+            serde_json::json!({ "command": "op steve" })
+        );
+        assert_eq!(entries[0]["outcome"], "blocked");
+        assert!(entries[0].get("detail").is_none());
+        assert!(entries[0]["timestamp"]
+            .as_str()
+            .is_some_and(|t| t.ends_with('Z')));
+
+        assert_eq!(entries[1]["command"], "/restore (confirmed)");
+        assert_eq!(
+            entries[1]["arguments"],
+            serde_json::json!({ "server": "survival", "backup": "backup.tar.gz" })
+        );
+        assert_eq!(entries[1]["outcome"], "failed");
+        assert_eq!(entries[1]["detail"], "the server didn't stop");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reconfigure_switches_file() {
+        let (audit, path) = audit_log("reconfigure");
+        let (_, new_path) = audit_log("reconfigured");
+
+        audit
+            .record_console(&user(), "list", Outcome::Success, None)
+            .await;
+        audit.reconfigure(AuditConfig {
+            path: new_path.clone(),
+            channel_id: None,
+        });
+        audit
+            .record_console(&user(), "list", Outcome::Denied, None)
+            .await;
+
+        assert_eq!(read_entries(&path)[0]["outcome"], "success");
+        assert_eq!(read_entries(&new_path)[0]["outcome"], "denied");
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&new_path).unwrap();
+    }
+
+    #[test]
+    fn converts_options() {
+        let options = vec![
+            CommandDataOption {
+                name: "server".to_string(),
+                value: CommandOptionValue::String("survival".to_string()),
+            },
+            CommandDataOption {
+                name: "pause".to_string(),
+                value: CommandOptionValue::SubCommand(vec![CommandDataOption {
+                    name: "job".to_string(),
+                    value: CommandOptionValue::String("nightly".to_string()),
+                }]),
+            },
+            CommandDataOption {
+                name: "count".to_string(),
+                value: CommandOptionValue::Integer(3),
+            },
+        ];
+
+        assert_eq!(
+            Value::Object(options_to_json(&options)),
+            serde_json::json!({
+                "server": "survival",
+                "pause": { "job": "nightly" },
+                "count": 3,
+            })
+        );
+    }
+}
@@ -1,54 +1,160 @@
-use crate::discord::{handle_interaction, log_stdout, manage_status, set_commands, set_status};
-use crate::minecraft::{ServerManager, ServerStatus};
+use crate::audit::AuditLog;
+use crate::config::Config;
+use crate::discord::{
+    escape_markdown, handle_interaction, handle_message, log_stdout, manage_status, post_message,
+    render_chat_event, render_event, set_commands, set_status, Context, Server,
+};
+use crate::minecraft::{ping, ServerCommand, ServerEvent, ServerManager, ServerStatus};
+use crate::schedule::{self, JobStates};
+use chrono::Utc;
 use log::{info, warn};
 use std::fmt::Write;
-use std::time::Duration;
-use std::{env, sync::Arc};
-use tokio::sync::{broadcast, RwLock};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::signal::{self, unix::SignalKind};
+use tokio::sync::{broadcast, oneshot, watch, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time;
 use twilight_gateway::{Event, Intents, MessageSender};
 use twilight_gateway::{Shard, ShardId};
 use twilight_http::Client;
 use twilight_model::id::{marker::ChannelMarker, Id};
 
-pub async fn init() -> Result<(), anyhow::Error> {
-    let token = env::var("DISCORD_TOKEN").expect("");
+/// How often the idle shutdown checks if anyone is online.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Longest time the scheduler sleeps at once, so changes of the system clock are picked up.
+const SCHEDULER_MAX_SLEEP: Duration = Duration::from_secs(60);
 
-    let mut shard = Shard::new(ShardId::ONE, token.clone(), Intents::empty());
+pub async fn init(config: Config) -> Result<(), anyhow::Error> {
+    let config = Arc::new(config);
+    let (config_sender, config_receiver) = watch::channel(config.clone());
+
+    // Reading the chat and console channel requires the privileged message content intent
+    let intents = if config.reads_messages() {
+        Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT
+    } else {
+        Intents::empty()
+    };
+    let mut shard = Shard::new(ShardId::ONE, config.discord.token.clone(), intents);
     let discord_msg_sender = shard.sender();
 
-    let client = Arc::new(Client::new(token));
-    let (server, cmd_sender, stout_receiver) = ServerManager::new();
+    let client = Arc::new(Client::new(config.discord.token.clone()));
+
+    let mut servers = Vec::new();
+    let mut event_receivers = Vec::new();
+    for profile in &config.servers {
+        let (manager, cmd_sender, event_receiver) = ServerManager::new(profile.manager_settings());
+        servers.push(Server {
+            name: profile.name.clone(),
+            manager,
+            cmd_sender,
+            status: Arc::new(RwLock::new(ServerStatus::Offline)),
+            jobs: RwLock::new(JobStates::default()),
+        });
+        event_receivers.push(event_receiver);
+    }
+    let servers = Arc::new(servers);
 
     let application_id = client.current_user_application().await?.model().await?.id;
 
-    tokio::spawn(set_commands(application_id, client.clone()));
+    tokio::spawn(set_commands(
+        application_id,
+        client.clone(),
+        config.discord.dev_guild_id,
+    ));
 
-    let status = Arc::new(RwLock::new(ServerStatus::Offline));
+    let mut message_handles = Vec::new();
+    for (index, event_receiver) in event_receivers.into_iter().enumerate() {
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let message_handle = message_receiver(
+            event_receiver,
+            shutdown_receiver,
+            discord_msg_sender.clone(),
+            servers.clone(),
+            index,
+            client.clone(),
+            config_receiver.clone(),
+        );
+        message_handles.push((shutdown_sender, message_handle));
+        status_poller(
+            discord_msg_sender.clone(),
+            servers.clone(),
+            index,
+            config_receiver.clone(),
+        );
+        idle_shutdown(
+            servers.clone(),
+            index,
+            client.clone(),
+            config_receiver.clone(),
+        );
+        wake_on_join(servers.clone(), index, config_receiver.clone());
+        scheduler(
+            servers.clone(),
+            index,
+            client.clone(),
+            config_receiver.clone(),
+        );
+        chat_bridge(
+            servers[index].manager.subscribe(),
+            servers[index].name.clone(),
+            client.clone(),
+            config_receiver.clone(),
+        );
+    }
 
-    message_receiver(
-        stout_receiver,
-        discord_msg_sender.clone(),
-        status.clone(),
+    for (server, profile) in servers.iter().zip(&config.servers) {
+        server.manager.attach(profile.server_config()).await;
+    }
+
+    let ctx = Arc::new(Context {
+        app_id: application_id,
+        client: client.clone(),
+        servers: servers.clone(),
+        audit: Arc::new(AuditLog::new(config.audit.clone(), client.clone())),
+        config: config_receiver,
+    });
+    config_reloader(
+        config_sender,
+        servers.clone(),
+        ctx.audit.clone(),
         client.clone(),
     );
 
+    let shutdown_signal = shutdown_signal();
+    tokio::pin!(shutdown_signal);
+
     loop {
-        match shard.next_event().await {
+        let event = tokio::select! {
+            event = shard.next_event() => event,
+            _ = &mut shutdown_signal => {
+                info!("Shutting down...");
+                break;
+            }
+        };
+
+        match event {
             Ok(event) => match event {
+                // A slow command, like one over RCON, mustn't hold up the others past Discord's deadline
                 Event::InteractionCreate(interaction) => {
-                    handle_interaction(
-                        application_id,
-                        client.clone(),
-                        server.clone(),
-                        cmd_sender.clone(),
-                        interaction,
-                    )
-                    .await?;
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = handle_interaction(&ctx, interaction).await {
+                            warn!("Failed handling interaction: {err}");
+                        }
+                    });
+                }
+                // Console input waits for the server to take each line
+                Event::MessageCreate(message) => {
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        handle_message(&ctx, message).await;
+                    });
                 }
                 Event::Ready(_) => {
                     info!("Bot started!");
-                    set_status(&discord_msg_sender, *status.read().await).await;
+                    set_status(&discord_msg_sender, &servers).await;
                 }
                 _ => {}
             },
@@ -64,35 +170,110 @@ pub async fn init() -> Result<(), anyhow::Error> {
         };
     }
 
+    // Stop the servers at the same time, so the shutdown takes as long as the slowest server
+    let config = ctx.config.borrow().clone();
+    let stop_handles: Vec<_> = servers
+        .iter()
+        .map(|server| {
+            let profile = config
+                .server(&server.name)
+                .expect("Servers only change when EVE restarts");
+            let manager = server.manager.clone();
+            let name = server.name.clone();
+            let (keep_running, shutdown_timeout) = (profile.keep_running, profile.shutdown_timeout);
+            tokio::spawn(async move {
+                if keep_running {
+                    info!("Leaving Minecraft server {name} running");
+                } else {
+                    manager.stop(shutdown_timeout).await;
+                }
+            })
+        })
+        .collect();
+    for handle in stop_handles {
+        handle
+            .await
+            .unwrap_or_else(|err| warn!("Failed joining server shutdown: {err}"));
+    }
+
+    for (shutdown_sender, message_handle) in message_handles {
+        let _ = shutdown_sender.send(());
+        message_handle
+            .await
+            .unwrap_or_else(|err| warn!("Failed joining message receiver: {err}"));
+    }
+
     Ok(())
 }
 
+async fn shutdown_signal() {
+    let mut sigterm =
+        signal::unix::signal(SignalKind::terminate()).expect("Failed listening for SIGTERM");
+
+    tokio::select! {
+        _ = signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+}
+
 fn message_receiver(
-    mut stdout_receiver: broadcast::Receiver<String>,
+    mut event_receiver: broadcast::Receiver<ServerEvent>,
+    mut shutdown_receiver: oneshot::Receiver<()>,
     discord_msg_sender: MessageSender,
-    status: Arc<RwLock<ServerStatus>>,
+    servers: Arc<Vec<Server>>,
+    index: usize,
     client: Arc<Client>,
-) {
-    let channel_id: Id<ChannelMarker> =
-        Id::new(env::var("CONSOLE_CHANNEL_ID").expect("").parse().expect(""));
-    let max_players: Option<u8> = env::var("MAX_PLAYERS")
-        .ok()
-        .map(|max| max.parse().expect(""));
-
+    config: watch::Receiver<Arc<Config>>,
+) -> JoinHandle<()> {
     tokio::spawn(async move {
+        let server = &servers[index];
+        let console_channel_id = || {
+            config
+                .borrow()
+                .server(&server.name)
+                .expect("Servers only change when EVE restarts")
+                .console_channel_id
+        };
         let cache = Arc::new(RwLock::new(String::new()));
         let timeout = Arc::new(RwLock::new(false));
 
-        while let Ok(msg) = stdout_receiver.recv().await {
-            let old_status = *status.read().await;
-            let new_status =
-                manage_status(&discord_msg_sender, old_status, max_players, &msg).await;
+        loop {
+            let event = tokio::select! {
+                biased;
+                event = event_receiver.recv() => match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Console forwarding skipped {skipped} server events");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = &mut shutdown_receiver => {
+                    // Everything the server printed until now is already buffered in the channel
+                    let mut cache_w = cache.write().await;
+                    while let Ok(event) = event_receiver.try_recv() {
+                        if let Some(msg) = render_event(&event) {
+                            write!(cache_w, "\n{msg}").unwrap_or_else(|err| {
+                                warn!("Failed to write logs to cache string: {err}")
+                            });
+                        }
+                    }
+                    break;
+                }
+            };
+
+            let old_status = server.status.read().await.clone();
+            let new_status = manage_status(old_status.clone(), &event);
 
             if new_status != old_status {
-                let mut status = status.write().await;
-                *status = new_status;
+                *server.status.write().await = new_status;
+                set_status(&discord_msg_sender, &servers).await;
             }
 
+            let Some(msg) = render_event(&event) else {
+                continue;
+            };
+
             let mut cache_w = cache.write().await;
             write!(cache_w, "\n{msg}")
                 .unwrap_or_else(|err| warn!("Failed to write logs to cache string: {err}"));
@@ -101,19 +282,395 @@ fn message_receiver(
                 let mut timeout_w = timeout.write().await;
                 *timeout_w = true;
 
-                await_log_cache(channel_id, cache.clone(), timeout.clone(), client.clone());
+                await_log_cache(
+                    console_channel_id(),
+                    cache.clone(),
+                    timeout.clone(),
+                    client.clone(),
+                );
             }
         }
 
         let cache = cache.read().await;
         if !cache.is_empty() {
-            log_stdout(client, cache.to_string(), channel_id)
+            log_stdout(client.clone(), cache.to_string(), console_channel_id())
+                .await
+                .unwrap_or_else(|err| warn!("Failed to send logs to Discord channel: {err}"));
+        }
+    })
+}
+
+/// Posts chat messages, joins, leaves, deaths and advancements into the chat channel of the server.
+fn chat_bridge(
+    mut event_receiver: broadcast::Receiver<ServerEvent>,
+    name: String,
+    client: Arc<Client>,
+    config: watch::Receiver<Arc<Config>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let event = match event_receiver.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Chat bridge skipped {skipped} server events");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            let Some(channel_id) = config
+                .borrow()
+                .server(&name)
+                .expect("Servers only change when EVE restarts")
+                .chat_channel_id
+            else {
+                continue;
+            };
+            if let Some(msg) = render_chat_event(&event) {
+                post_message(&client, &msg, channel_id)
+                    .await
+                    .unwrap_or_else(|err| warn!("Failed to send message to chat channel: {err}"));
+            }
+        }
+    });
+}
+
+/// Periodically pings the server while it's running to keep player count, version and MOTD up to date.
+fn status_poller(
+    discord_msg_sender: MessageSender,
+    servers: Arc<Vec<Server>>,
+    index: usize,
+    config: watch::Receiver<Arc<Config>>,
+) {
+    tokio::spawn(async move {
+        let server = &servers[index];
+        loop {
+            let (poll_interval, address) = {
+                let config = config.borrow();
+                let profile = config
+                    .server(&server.name)
+                    .expect("Servers only change when EVE restarts");
+                (profile.status_poll_interval, profile.address.clone())
+            };
+            time::sleep(poll_interval).await;
+            if !matches!(*server.status.read().await, ServerStatus::Running { .. }) {
+                continue;
+            }
+
+            let info = match time::timeout(Duration::from_secs(5), ping(&address)).await {
+                Ok(Ok(info)) => info,
+                Ok(Err(err)) => {
+                    warn!("Failed pinging Minecraft server {}: {err}", server.name);
+                    continue;
+                }
+                Err(_) => {
+                    warn!("Pinging Minecraft server {} timed out", server.name);
+                    continue;
+                }
+            };
+
+            let mut status = server.status.write().await;
+            let new_status = ServerStatus::Running { info: Some(info) };
+            // The server might have stopped while pinging
+            if matches!(*status, ServerStatus::Running { .. }) && *status != new_status {
+                *status = new_status;
+                drop(status);
+                set_status(&discord_msg_sender, &servers).await;
+            }
+        }
+    });
+}
+
+/// Stops the server once nobody was online for `idle.timeout`. The shutdown is announced in the console and
+/// chat channel first, a player joining within the grace period cancels it.
+fn idle_shutdown(
+    servers: Arc<Vec<Server>>,
+    index: usize,
+    client: Arc<Client>,
+    config: watch::Receiver<Arc<Config>>,
+) {
+    tokio::spawn(async move {
+        let server = &servers[index];
+        let mut empty_since = None;
+
+        loop {
+            time::sleep(IDLE_CHECK_INTERVAL).await;
+            let (idle, channels) = {
+                let config = config.borrow();
+                let profile = config
+                    .server(&server.name)
+                    .expect("Servers only change when EVE restarts");
+                (
+                    profile.idle.clone(),
+                    [Some(profile.console_channel_id), profile.chat_channel_id],
+                )
+            };
+
+            // Without a ping, the player count is unknown
+            let empty = matches!(
+                &*server.status.read().await,
+                ServerStatus::Running { info: Some(info) } if info.players == 0
+            );
+            let Some(idle) = idle.filter(|_| empty) else {
+                empty_since = None;
+                continue;
+            };
+            let since = *empty_since.get_or_insert_with(Instant::now);
+            if since.elapsed() < idle.timeout {
+                continue;
+            }
+            empty_since = None;
+
+            let announce = |msg: String| {
+                let client = client.clone();
+                async move {
+                    for channel_id in channels.into_iter().flatten() {
+                        post_message(&client, &msg, channel_id)
+                            .await
+                            .unwrap_or_else(|err| warn!("Failed to announce idle shutdown: {err}"));
+                    }
+                }
+            };
+
+            info!(
+                "Nobody is online on Minecraft server {}, stopping it in {}s",
+                server.name,
+                idle.grace_period.as_secs()
+            );
+            let mut events = server.manager.subscribe();
+            announce(format!(
+                ":zzz: Nobody was online for {} minutes, stopping the server in {}s. Join to keep it running",
+                (idle.timeout.as_secs() / 60).max(1),
+                idle.grace_period.as_secs()
+            ))
+            .await;
+
+            let joined = tokio::select! {
+                _ = time::sleep(idle.grace_period) => None,
+                player = next_join(&mut events) => Some(player),
+            };
+            if let Some(player) = joined {
+                info!("{player} joined, cancelled idle shutdown");
+                announce(format!(
+                    ":arrow_right: **{}** joined, the server keeps running",
+                    escape_markdown(&player)
+                ))
+                .await;
+                continue;
+            }
+            if !matches!(*server.status.read().await, ServerStatus::Running { .. }) {
+                continue;
+            }
+
+            // The manager handles commands in order, so the server stops once the backup is done
+            if idle.backup {
+                server
+                    .cmd_sender
+                    .send(ServerCommand::Backup)
+                    .await
+                    .expect("Failed sending value over sender");
+            }
+            server
+                .cmd_sender
+                .send(ServerCommand::Stop)
+                .await
+                .expect("Failed sending value over sender");
+        }
+    });
+}
+
+/// Starts the server when a player tries to join it while it's offline, see `wake.enabled`.
+fn wake_on_join(servers: Arc<Vec<Server>>, index: usize, config: watch::Receiver<Arc<Config>>) {
+    tokio::spawn(async move {
+        let server = &servers[index];
+        let mut event_receiver = server.manager.subscribe();
+        loop {
+            match event_receiver.recv().await {
+                Ok(ServerEvent::WakeRequested { .. }) => {}
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+
+            let server_config = config
+                .borrow()
+                .server(&server.name)
+                .expect("Servers only change when EVE restarts")
+                .server_config();
+            server
+                .cmd_sender
+                .send(ServerCommand::StartServer {
+                    config: server_config,
+                })
+                .await
+                .expect("Failed sending value over sender");
+        }
+    });
+}
+
+/// Runs the jobs of the server whenever their cron expression matches. Paused jobs are left out, jobs
+/// whose server isn't in the right state are skipped.
+fn scheduler(
+    servers: Arc<Vec<Server>>,
+    index: usize,
+    client: Arc<Client>,
+    mut config: watch::Receiver<Arc<Config>>,
+) {
+    tokio::spawn(async move {
+        let server = &servers[index];
+        let mut from = Utc::now();
+
+        loop {
+            let (profile, timezone) = {
+                let config = config.borrow_and_update();
+                let profile = config
+                    .server(&server.name)
+                    .expect("Servers only change when EVE restarts")
+                    .clone();
+                (profile, config.timezone)
+            };
+            let after = from.with_timezone(&timezone);
+            let Some((next, due)) = schedule::next_due(&profile.jobs, &after) else {
+                if config.changed().await.is_err() {
+                    break;
+                }
+                from = Utc::now();
+                continue;
+            };
+
+            let wait = (next.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            tokio::select! {
+                _ = time::sleep(wait.min(SCHEDULER_MAX_SLEEP)) => {}
+                changed = config.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    // Jobs which are due until now are left out
+                    from = Utc::now();
+                    continue;
+                }
+            }
+            if Utc::now() < next {
+                continue;
+            }
+            from = next.with_timezone(&Utc);
+
+            for job in due {
+                if server.jobs.read().await.paused(&job.name) {
+                    info!("Job {} of {} is paused", job.name, server.name);
+                    continue;
+                }
+                if schedule::run(job, server, &profile).await.is_ok() {
+                    let msg = format!(
+                        ":alarm_clock: Running scheduled job `{}` ({})",
+                        job.name, job.action
+                    );
+                    post_message(&client, &msg, profile.console_channel_id)
+                        .await
+                        .unwrap_or_else(|err| {
+                            warn!("Failed to send message to console channel: {err}")
+                        });
+                }
+            }
+        }
+    });
+}
+
+/// Waits for the next player to join, forever if the server is gone.
+async fn next_join(events: &mut broadcast::Receiver<ServerEvent>) -> String {
+    loop {
+        match events.recv().await {
+            Ok(ServerEvent::PlayerJoined { player }) => return player,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
+/// Reloads the config when its file changes or EVE receives `SIGHUP`, applies it and posts the changes
+/// into the console channel of the first server. An invalid config is reported and the current one kept.
+fn config_reloader(
+    config_sender: watch::Sender<Arc<Config>>,
+    servers: Arc<Vec<Server>>,
+    audit: Arc<AuditLog>,
+    client: Arc<Client>,
+) {
+    tokio::spawn(async move {
+        let mut sighup =
+            signal::unix::signal(SignalKind::hangup()).expect("Failed listening for SIGHUP");
+        let mut modified = config_modified();
+
+        loop {
+            tokio::select! {
+                _ = sighup.recv() => info!("Received SIGHUP, reloading config..."),
+                _ = time::sleep(Duration::from_secs(2)) => {
+                    let new_modified = config_modified();
+                    if new_modified == modified {
+                        continue;
+                    }
+                    modified = new_modified;
+                    info!("Config file changed, reloading config...");
+                }
+            }
+
+            let current = config_sender.borrow().clone();
+            let msg = match Config::load() {
+                Ok(mut config) => {
+                    let changes = current.changes(&config);
+                    if changes.is_empty() {
+                        info!("Config didn't change");
+                        continue;
+                    }
+                    config.keep_servers(&current);
+                    for server in servers.iter() {
+                        let profile = config
+                            .server(&server.name)
+                            .expect("Servers only change when EVE restarts");
+                        server.manager.reconfigure(profile.manager_settings()).await;
+                    }
+                    audit.reconfigure(config.audit.clone());
+                    config_sender.send_replace(Arc::new(config));
+                    info!("Reloaded config");
+
+                    format!(
+                        ":gear: Reloaded the config\n{}",
+                        changes
+                            .iter()
+                            .map(|change| format!("- {change}"))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    )
+                }
+                Err(errors) => {
+                    for err in &errors {
+                        warn!("{err}");
+                    }
+                    format!(
+                        ":warning: Failed reloading the config, keeping the current one\n{}",
+                        errors
+                            .iter()
+                            .map(|err| format!("- {err}"))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    )
+                }
+            };
+
+            let channel_id = config_sender.borrow().servers[0].console_channel_id;
+            log_stdout(client.clone(), msg, channel_id)
                 .await
                 .unwrap_or_else(|err| warn!("Failed to send logs to Discord channel: {err}"));
         }
     });
 }
 
+fn config_modified() -> Option<SystemTime> {
+    Config::path()
+        .and_then(|path| fs::metadata(path).ok())
+        .and_then(|metadata| metadata.modified().ok())
+}
+
 fn await_log_cache(
     channel_id: Id<ChannelMarker>,
     cached: Arc<RwLock<String>>,
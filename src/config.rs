@@ -0,0 +1,1470 @@
+use crate::minecraft::{
+    split_args, BackupConfig, Compression, JvmPreset, Launch, ManagerSettings, RconConfig,
+    RestartPolicy, Retention, ServerConfig, WakeConfig,
+};
+use crate::permissions::{Level, Permissions};
+use crate::schedule::{Action, Job};
+use astrolabe::DateTime;
+use chrono_tz::Tz;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashSet,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use toml::{Table, Value};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker},
+    Id,
+};
+
+const DEFAULT_PATH: &str = "eve.toml";
+
+/// Name of the server profile configured without `[servers]`.
+const DEFAULT_SERVER: &str = "default";
+
+/// Keys and env vars of the server settings when there's only one server, configured without `[servers]`.
+const SINGLE_SERVER_KEYS: [(&str, &str, &str); 40] = [
+    (
+        "console_channel_id",
+        "discord.console_channel_id",
+        "CONSOLE_CHANNEL_ID",
+    ),
+    (
+        "chat_channel_id",
+        "discord.chat_channel_id",
+        "CHAT_CHANNEL_ID",
+    ),
+    ("jar_path", "server.jar_path", "SERVER_JAR_PATH"),
+    ("command", "server.command", "SERVER_COMMAND"),
+    ("working_dir", "server.working_dir", "SERVER_WORKING_DIR"),
+    ("memory", "server.memory", "SERVER_MEMORY"),
+    ("java_path", "server.java_path", "JAVA_PATH"),
+    ("jvm_preset", "server.jvm_preset", "JVM_PRESET"),
+    ("jvm_args", "server.jvm_args", "JVM_ARGS"),
+    ("jvm_flags", "server.jvm_flags", "JVM_FLAGS"),
+    ("server_args", "server.server_args", "SERVER_ARGS"),
+    (
+        "auto_accept_eula",
+        "server.auto_accept_eula",
+        "AUTO_ACCEPT_EULA",
+    ),
+    ("address", "server.address", "SERVER_ADDRESS"),
+    (
+        "status_poll_interval",
+        "server.status_poll_interval",
+        "STATUS_POLL_INTERVAL",
+    ),
+    ("stop_timeout", "server.stop_timeout", "STOP_TIMEOUT"),
+    (
+        "shutdown_timeout",
+        "server.shutdown_timeout",
+        "SHUTDOWN_TIMEOUT",
+    ),
+    (
+        "restart_countdown",
+        "server.restart_countdown",
+        "RESTART_COUNTDOWN",
+    ),
+    ("keep_running", "server.keep_running", "KEEP_SERVER_RUNNING"),
+    (
+        "restart.max_crashes",
+        "restart.max_crashes",
+        "RESTART_MAX_CRASHES",
+    ),
+    (
+        "restart.crash_window",
+        "restart.crash_window",
+        "RESTART_CRASH_WINDOW",
+    ),
+    ("restart.backoff", "restart.backoff", "RESTART_BACKOFF"),
+    ("rcon.address", "rcon.address", "RCON_ADDRESS"),
+    ("rcon.password", "rcon.password", "RCON_PASSWORD"),
+    ("backup.folder", "backup.folder", "BACKUP_FOLDER"),
+    (
+        "backup.server_folder",
+        "backup.server_folder",
+        "SERVER_FOLDER",
+    ),
+    ("backup.name", "backup.name", "BACKUP_NAME"),
+    ("backup.command", "backup.command", "BACKUP_COMMAND"),
+    (
+        "backup.retention.keep_last",
+        "backup.retention.keep_last",
+        "BACKUP_KEEP_LAST",
+    ),
+    (
+        "backup.retention.keep_daily",
+        "backup.retention.keep_daily",
+        "BACKUP_KEEP_DAILY",
+    ),
+    (
+        "backup.retention.keep_weekly",
+        "backup.retention.keep_weekly",
+        "BACKUP_KEEP_WEEKLY",
+    ),
+    (
+        "backup.retention.keep_monthly",
+        "backup.retention.keep_monthly",
+        "BACKUP_KEEP_MONTHLY",
+    ),
+    (
+        "backup.retention.max_size",
+        "backup.retention.max_size",
+        "BACKUP_MAX_SIZE",
+    ),
+    (
+        "backup.retention.dry_run",
+        "backup.retention.dry_run",
+        "BACKUP_RETENTION_DRY_RUN",
+    ),
+    ("idle.timeout", "idle.timeout", "IDLE_TIMEOUT"),
+    (
+        "idle.grace_period",
+        "idle.grace_period",
+        "IDLE_GRACE_PERIOD",
+    ),
+    ("idle.backup", "idle.backup", "IDLE_BACKUP"),
+    ("wake.enabled", "wake.enabled", "WAKE_ON_JOIN"),
+    ("wake.address", "wake.address", "WAKE_ADDRESS"),
+    ("wake.motd", "wake.motd", "WAKE_MOTD"),
+    (
+        "wake.kick_message",
+        "wake.kick_message",
+        "WAKE_KICK_MESSAGE",
+    ),
+];
+
+/// Settings which are only used when launching the server.
+const LAUNCH_SETTINGS: [&str; 9] = [
+    "jar_path",
+    "java_path",
+    "command",
+    "working_dir",
+    "memory",
+    "jvm_preset",
+    "jvm_args",
+    "server_args",
+    "auto_accept_eula",
+];
+/// Settings which are only used when EVE starts.
+const RESTART_SETTINGS: [&str; 2] = ["discord.token", "discord.dev_guild_id"];
+/// Settings whose values must not show up in Discord.
+const SECRET_SETTINGS: [&str; 3] = ["discord.token", "rcon.password", "backup.command"];
+/// Settings too long to show in Discord, only reported as changed.
+const SUMMARIZED_SETTINGS: [&str; 1] = ["jobs"];
+
+/// Configuration of EVE, loaded from `eve.toml`. Every setting of EVE and of a single server can be
+/// overridden with the environment variable noted next to it.
+#[derive(Debug)]
+pub(crate) struct Config {
+    pub(crate) discord: DiscordConfig,
+    /// Servers in the order they're configured in.
+    pub(crate) servers: Vec<ServerProfile>,
+    pub(crate) permissions: Permissions,
+    pub(crate) audit: AuditConfig,
+    /// Timezone the cron expressions of the jobs are evaluated in.
+    pub(crate) timezone: Tz,
+}
+
+#[derive(Debug)]
+pub(crate) struct DiscordConfig {
+    pub(crate) token: String,
+    /// IDs of the users and roles allowed to type commands into the console channels.
+    pub(crate) console_input_allowed: Vec<u64>,
+    /// Registers the commands for this guild only instead of globally, used for development.
+    pub(crate) dev_guild_id: Option<Id<GuildMarker>>,
+}
+
+/// A Minecraft server managed by EVE.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerProfile {
+    pub(crate) name: String,
+    pub(crate) console_channel_id: Id<ChannelMarker>,
+    pub(crate) chat_channel_id: Option<Id<ChannelMarker>>,
+    pub(crate) launch: Launch,
+    /// Folder the server runs in.
+    pub(crate) working_dir: PathBuf,
+    /// Memory in megabytes, only optional for custom launch commands.
+    pub(crate) memory: Option<u16>,
+    pub(crate) jvm_preset: JvmPreset,
+    pub(crate) jvm_args: Vec<String>,
+    pub(crate) server_args: Vec<String>,
+    pub(crate) auto_accept_eula: bool,
+    pub(crate) address: String,
+    pub(crate) status_poll_interval: Duration,
+    pub(crate) stop_timeout: Duration,
+    pub(crate) shutdown_timeout: Duration,
+    pub(crate) restart_countdown: Vec<Duration>,
+    pub(crate) keep_running: bool,
+    pub(crate) restart_policy: RestartPolicy,
+    pub(crate) rcon: Option<RconConfig>,
+    pub(crate) backup: Option<BackupConfig>,
+    pub(crate) idle: Option<IdleShutdown>,
+    pub(crate) wake: Option<WakeConfig>,
+    pub(crate) jobs: Vec<Job>,
+}
+
+/// Stops the server once nobody was online for a while.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IdleShutdown {
+    pub(crate) timeout: Duration,
+    /// Time players have to join after the shutdown was announced.
+    pub(crate) grace_period: Duration,
+    /// Creates a backup before stopping.
+    pub(crate) backup: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AuditConfig {
+    pub(crate) path: PathBuf,
+    pub(crate) channel_id: Option<Id<ChannelMarker>>,
+}
+
+impl Config {
+    /// Loads the config file set by `EVE_CONFIG`, or `eve.toml` if it exists, and applies the
+    /// environment variables on top. Returns every problem found instead of stopping at the first one.
+    pub(crate) fn load() -> Result<Self, Vec<String>> {
+        let path = Self::path();
+        let table = match &path {
+            Some(path) => read_table(path)?,
+            // Without a config file, everything is configured with env vars
+            None => Table::new(),
+        };
+
+        let file = path
+            .unwrap_or(PathBuf::from(DEFAULT_PATH))
+            .display()
+            .to_string();
+        Self::from_table(table, file, |name| env::var(name).ok())
+    }
+
+    /// Loads the config from the parsed file, `var` looks up the environment variables.
+    fn from_table(
+        table: Table,
+        file: String,
+        var: impl Fn(&str) -> Option<String> + 'static,
+    ) -> Result<Self, Vec<String>> {
+        let mut loader = Loader {
+            table,
+            file,
+            var: Box::new(var),
+            known: HashSet::new(),
+            errors: Vec::new(),
+        };
+        let config = Self::from_loader(&mut loader);
+        loader.check_unknown_keys();
+
+        match config {
+            Some(config) if loader.errors.is_empty() => Ok(config),
+            _ => Err(loader.errors),
+        }
+    }
+
+    /// Path of the config file, `None` if there is none.
+    pub(crate) fn path() -> Option<PathBuf> {
+        match env::var("EVE_CONFIG").ok().filter(|path| !path.is_empty()) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(DEFAULT_PATH)).filter(|path| path.exists()),
+        }
+    }
+
+    fn from_loader(l: &mut Loader) -> Option<Self> {
+        let token = l.required("discord.token", "DISCORD_TOKEN");
+        let console_input_allowed = l
+            .list("discord.console_input_allowed", "CONSOLE_INPUT_ALLOWED")
+            .unwrap_or_default();
+        let dev_guild_id = l.id(
+            "discord.dev_guild_id",
+            if (l.var)("DEV").is_some() {
+                "GUILD_ID"
+            } else {
+                ""
+            },
+        );
+
+        let servers = match l.table.get("servers") {
+            Some(Value::Table(servers)) => {
+                let names: Vec<String> = servers.keys().cloned().collect();
+                if names.is_empty() {
+                    l.error("`servers` has to contain at least one server");
+                }
+                names
+                    .into_iter()
+                    .map(|name| ServerProfile::from_loader(l, Some(name)))
+                    .collect()
+            }
+            Some(_) => {
+                l.error(format!("`servers` in {} has to be a table", l.file));
+                Vec::new()
+            }
+            None => vec![ServerProfile::from_loader(l, None)],
+        };
+        let servers: Vec<ServerProfile> = servers.into_iter().flatten().collect();
+
+        for (i, server) in servers.iter().enumerate() {
+            for other in &servers[i + 1..] {
+                if server.console_channel_id == other.console_channel_id {
+                    l.error(format!(
+                        "Servers `{}` and `{}` can't share a console channel",
+                        server.name, other.name
+                    ));
+                }
+                if server.chat_channel_id.is_some()
+                    && server.chat_channel_id == other.chat_channel_id
+                {
+                    l.error(format!(
+                        "Servers `{}` and `{}` can't share a chat channel",
+                        server.name, other.name
+                    ));
+                }
+            }
+        }
+
+        let commands = l
+            .map("permissions.commands", "COMMAND_PERMISSIONS")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(command, level)| match level.parse::<Level>() {
+                Ok(level) => Some((command.to_lowercase(), level)),
+                Err(_) => {
+                    l.error(format!(
+                        "`permissions.commands.{command}`: `{level}` is not a level, use `everyone`, `moderator` or `admin`"
+                    ));
+                    None
+                }
+            })
+            .collect();
+        let permissions = Permissions::new(
+            l.list("permissions.moderators", "MODERATORS")
+                .unwrap_or_default(),
+            l.list("permissions.admins", "ADMINS").unwrap_or_default(),
+            commands,
+            l.list("permissions.send_allow", "SEND_ALLOW")
+                .unwrap_or_default(),
+            l.list("permissions.send_deny", "SEND_DENY")
+                .unwrap_or_default(),
+        );
+
+        let audit = AuditConfig {
+            path: l
+                .get("audit.path", "AUDIT_LOG_PATH")
+                .unwrap_or(PathBuf::from("audit.jsonl")),
+            channel_id: l.id("audit.channel_id", "AUDIT_CHANNEL_ID"),
+        };
+
+        let timezone = l
+            .get::<String>("schedule.timezone", "SCHEDULE_TIMEZONE")
+            .and_then(|timezone| match timezone.parse() {
+                Ok(timezone) => Some(timezone),
+                Err(_) => {
+                    l.error(format!(
+                        "`schedule.timezone`: `{timezone}` is not a timezone, use a name like `Europe/Berlin`"
+                    ));
+                    None
+                }
+            })
+            .unwrap_or(Tz::UTC);
+
+        Some(Config {
+            discord: DiscordConfig {
+                token: token?,
+                console_input_allowed,
+                dev_guild_id,
+            },
+            servers,
+            permissions,
+            audit,
+            timezone,
+        })
+    }
+
+    pub(crate) fn server(&self, name: &str) -> Option<&ServerProfile> {
+        self.servers.iter().find(|server| server.name == name)
+    }
+
+    /// Adding and removing servers requires restarting EVE, until then the new config keeps the servers
+    /// of the current one.
+    pub(crate) fn keep_servers(&mut self, current: &Config) {
+        self.servers
+            .retain(|server| current.server(&server.name).is_some());
+        for server in &current.servers {
+            if self.server(&server.name).is_none() {
+                self.servers.push(server.clone());
+            }
+        }
+    }
+
+    /// Whether EVE has to read messages, which requires the privileged message content intent.
+    pub(crate) fn reads_messages(&self) -> bool {
+        !self.discord.console_input_allowed.is_empty()
+            || self
+                .servers
+                .iter()
+                .any(|server| server.chat_channel_id.is_some())
+    }
+
+    /// Describes every setting which differs in the new config, and when the change takes effect.
+    pub(crate) fn changes(&self, new: &Config) -> Vec<String> {
+        let mut changes = describe_changes(self.entries(), new.entries(), "");
+
+        for server in &self.servers {
+            match new.server(&server.name) {
+                Some(new_server) => changes.extend(describe_changes(
+                    server.entries(),
+                    new_server.entries(),
+                    &format!("{}: ", server.name),
+                )),
+                None => changes.push(format!(
+                    "Server `{}` was removed (requires restarting EVE)",
+                    server.name
+                )),
+            }
+        }
+        for server in &new.servers {
+            if self.server(&server.name).is_none() {
+                changes.push(format!(
+                    "Server `{}` was added (requires restarting EVE)",
+                    server.name
+                ));
+            }
+        }
+
+        if new.reads_messages() && !self.reads_messages() {
+            changes
+                .push("Reading the chat and console channels requires restarting EVE".to_string());
+        }
+        changes
+    }
+
+    fn entries(&self) -> Vec<(&'static str, String)> {
+        let discord = &self.discord;
+        let permissions = &self.permissions;
+        let commands = permissions
+            .commands()
+            .into_iter()
+            .map(|(command, level)| format!("{command}={level}"));
+        vec![
+            ("discord.token", discord.token.clone()),
+            (
+                "discord.console_input_allowed",
+                list(&discord.console_input_allowed),
+            ),
+            ("discord.dev_guild_id", optional(discord.dev_guild_id)),
+            ("permissions.moderators", list(permissions.moderators())),
+            ("permissions.admins", list(permissions.admins())),
+            ("permissions.commands", list(commands)),
+            ("permissions.send_allow", list(permissions.send_allow())),
+            ("permissions.send_deny", list(permissions.send_deny())),
+            ("audit.path", self.audit.path.display().to_string()),
+            ("audit.channel_id", optional(self.audit.channel_id)),
+            ("schedule.timezone", self.timezone.to_string()),
+        ]
+    }
+}
+
+impl ServerProfile {
+    /// Loads the server from `[servers.<name>]`, or from the single server settings without a name.
+    fn from_loader(l: &mut Loader, name: Option<String>) -> Option<Self> {
+        let key = |field: &str| -> (String, &'static str) {
+            match &name {
+                Some(name) => (format!("servers.{name}.{field}"), ""),
+                None => SINGLE_SERVER_KEYS
+                    .iter()
+                    .find(|(single_field, _, _)| *single_field == field)
+                    .map(|(_, key, env)| (key.to_string(), *env))
+                    .expect("Every server setting has a single server key"),
+            }
+        };
+        macro_rules! setting {
+            ($method:ident, $field:literal) => {{
+                let (key, env) = key($field);
+                l.$method(&key, env)
+            }};
+            ($method:ident::<$ty:ty>, $field:literal) => {{
+                let (key, env) = key($field);
+                l.$method::<$ty>(&key, env)
+            }};
+        }
+
+        let console_channel_id = setting!(required_id, "console_channel_id");
+        let chat_channel_id = setting!(id, "chat_channel_id");
+        let jar_path = setting!(get::<PathBuf>, "jar_path");
+        let command = setting!(args, "command");
+        let working_dir = setting!(get::<PathBuf>, "working_dir");
+        let memory = setting!(get::<u16>, "memory");
+        let java_path = setting!(get, "java_path").unwrap_or(PathBuf::from("java"));
+        let jvm_preset = setting!(get::<String>, "jvm_preset")
+            .and_then(|preset| match preset.parse() {
+                Ok(preset) => Some(preset),
+                Err(_) => {
+                    l.error(format!(
+                        "`{}`: `{preset}` is not a preset, use `default`, `aikar`, `aikar-large` or `zgc`",
+                        key("jvm_preset").0
+                    ));
+                    None
+                }
+            })
+            .unwrap_or_default();
+        // `jvm_flags` is the old name of `jvm_args`
+        let jvm_flags = setting!(args, "jvm_flags");
+        let jvm_args = setting!(args, "jvm_args");
+        if jvm_flags.is_some() && jvm_args.is_some() {
+            l.error(format!(
+                "`{}` replaces `{}`, remove the latter",
+                key("jvm_args").0,
+                key("jvm_flags").0
+            ));
+        }
+        let jvm_args = jvm_args.or(jvm_flags).unwrap_or_default();
+        let server_args = setting!(args, "server_args");
+        let auto_accept_eula = setting!(flag, "auto_accept_eula").unwrap_or(false);
+        let address = setting!(get, "address").unwrap_or("127.0.0.1:25565".to_string());
+        let status_poll_interval =
+            setting!(secs, "status_poll_interval").unwrap_or(Duration::from_secs(15));
+        let stop_timeout = setting!(secs, "stop_timeout").unwrap_or(Duration::from_secs(60));
+        let shutdown_timeout =
+            setting!(secs, "shutdown_timeout").unwrap_or(Duration::from_secs(60));
+        let restart_countdown = setting!(list::<u64>, "restart_countdown")
+            .unwrap_or(vec![60, 30, 10])
+            .into_iter()
+            .map(Duration::from_secs)
+            .collect();
+        let keep_running = setting!(flag, "keep_running").unwrap_or(false);
+
+        if memory == Some(0) {
+            l.error(format!("`{}` has to be greater than 0", key("memory").0));
+        }
+        let launch = match (jar_path, command) {
+            (Some(jar_path), None) => {
+                if !jar_path.is_file() {
+                    l.error(format!(
+                        "`{}` {} does not point to a file",
+                        key("jar_path").0,
+                        jar_path.display()
+                    ));
+                }
+                if memory.is_none() {
+                    l.error(format!(
+                        "`{}` is required to launch a server jar",
+                        key("memory").0
+                    ));
+                }
+                Some((
+                    Launch::Jar {
+                        path: jar_path,
+                        java_path,
+                    },
+                    // Servers started with `java -jar` open a GUI without `nogui`
+                    server_args.unwrap_or(vec!["nogui".to_string()]),
+                ))
+            }
+            (None, Some(command)) if command.is_empty() => {
+                l.error(format!("`{}` can't be empty", key("command").0));
+                None
+            }
+            // The placeholders can expand to nothing, which leaves no program to run
+            (None, Some(command))
+                if matches!(command[0].as_str(), "{JVM_ARGS}" | "{SERVER_ARGS}") =>
+            {
+                l.error(format!(
+                    "`{}` has to start with the program to run, not `{}`",
+                    key("command").0,
+                    command[0]
+                ));
+                None
+            }
+            (None, Some(command)) => {
+                if working_dir.is_none() {
+                    l.error(format!(
+                        "`{}` is required to launch the server with `{}`",
+                        key("working_dir").0,
+                        key("command").0
+                    ));
+                }
+                if memory.is_none() && command.iter().any(|arg| arg.contains("{MEMORY}")) {
+                    l.error(format!(
+                        "`{}` uses `{{MEMORY}}`, which requires `{}`",
+                        key("command").0,
+                        key("memory").0
+                    ));
+                }
+                Some((Launch::Command(command), server_args.unwrap_or_default()))
+            }
+            (Some(_), Some(_)) => {
+                l.error(format!(
+                    "Set either `{}` or `{}`, not both",
+                    key("jar_path").0,
+                    key("command").0
+                ));
+                None
+            }
+            (None, None) => {
+                let (jar_key, jar_env) = key("jar_path");
+                let (command_key, command_env) = key("command");
+                l.error(if jar_env.is_empty() {
+                    format!(
+                        "`{jar_key}` or `{command_key}` in {} is required",
+                        l.file
+                    )
+                } else {
+                    format!(
+                        "`{jar_key}` in {} or the {jar_env} env var is required, or `{command_key}`/{command_env} to launch the server with a custom command",
+                        l.file
+                    )
+                });
+                None
+            }
+        };
+        let working_dir = match (&launch, working_dir) {
+            (_, Some(working_dir)) => {
+                if !working_dir.is_dir() {
+                    l.error(format!(
+                        "`{}` {} is not a directory",
+                        key("working_dir").0,
+                        working_dir.display()
+                    ));
+                }
+                working_dir
+            }
+            (Some((Launch::Jar { path, .. }, _)), None) => path
+                .parent()
+                .filter(|folder| !folder.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            (_, None) => PathBuf::new(),
+        };
+
+        let restart_policy = RestartPolicy::new(
+            setting!(get, "restart.max_crashes").unwrap_or(3),
+            setting!(secs, "restart.crash_window").unwrap_or(Duration::from_secs(600)),
+            setting!(secs, "restart.backoff").unwrap_or(Duration::from_secs(5)),
+        );
+
+        let rcon_address = setting!(get, "rcon.address").unwrap_or("127.0.0.1:25575".to_string());
+        let rcon = setting!(get::<String>, "rcon.password")
+            .filter(|password| !password.is_empty())
+            .map(|password| RconConfig::new(rcon_address, password));
+
+        let backup_folder = setting!(get::<PathBuf>, "backup.folder");
+        let backup_server_folder = setting!(get::<PathBuf>, "backup.server_folder");
+        let backup_name = setting!(get, "backup.name")
+            .unwrap_or("'backup'_yyyy_MM_dd_HH_mm'.tar.gz'".to_string());
+        let backup_command = setting!(args, "backup.command");
+        if backup_command.as_ref().is_some_and(Vec::is_empty) {
+            l.error(format!("`{}` can't be empty", key("backup.command").0));
+        }
+        let backup_command = backup_command.filter(|command| !command.is_empty());
+        // The archive EVE writes is compressed according to the extension
+        let example_name = DateTime::now().format(&backup_name);
+        if backup_command.is_none() && Compression::from_name(&example_name).is_none() {
+            l.error(format!(
+                "`{}` `{backup_name}` has to end in `.tar`, `.tar.gz`, `.tgz`, `.tar.zst` or `.tzst`, e.g. `'backup'_yyyy_MM_dd_HH_mm'.tar.zst'`",
+                key("backup.name").0
+            ));
+        }
+        let retention = Retention {
+            keep_last: setting!(get, "backup.retention.keep_last"),
+            keep_daily: setting!(get, "backup.retention.keep_daily"),
+            keep_weekly: setting!(get, "backup.retention.keep_weekly"),
+            keep_monthly: setting!(get, "backup.retention.keep_monthly"),
+            // Configured in MB
+            max_size: setting!(get::<u64>, "backup.retention.max_size")
+                .map(|size| size.saturating_mul(1_000_000)),
+            dry_run: setting!(flag, "backup.retention.dry_run").unwrap_or(false),
+        };
+        let retention = (retention != Retention::default()).then_some(retention);
+        let backup = match (backup_folder, backup_server_folder) {
+            (Some(folder), Some(server_folder)) => {
+                for (field, folder) in [
+                    ("backup.folder", &folder),
+                    ("backup.server_folder", &server_folder),
+                ] {
+                    if !folder.is_dir() {
+                        l.error(format!(
+                            "`{}` {} is not a directory",
+                            key(field).0,
+                            folder.display()
+                        ));
+                    }
+                }
+                Some(BackupConfig::new(
+                    folder,
+                    server_folder,
+                    backup_name,
+                    backup_command,
+                    retention,
+                ))
+            }
+            (None, None) => {
+                if retention.is_some() {
+                    let (keep_last, _) = key("backup.retention.keep_last");
+                    l.error(format!(
+                        "`{}` requires backups to be configured",
+                        keep_last.trim_end_matches(".keep_last")
+                    ));
+                }
+                None
+            }
+            (Some(_), None) => {
+                l.error(format!(
+                    "`{}` is required when `{}` is set",
+                    key("backup.server_folder").0,
+                    key("backup.folder").0
+                ));
+                None
+            }
+            (None, Some(_)) => {
+                l.error(format!(
+                    "`{}` is required when `{}` is set",
+                    key("backup.folder").0,
+                    key("backup.server_folder").0
+                ));
+                None
+            }
+        };
+
+        let idle_timeout = setting!(secs, "idle.timeout").filter(|timeout| !timeout.is_zero());
+        let idle_grace_period =
+            setting!(secs, "idle.grace_period").unwrap_or(Duration::from_secs(60));
+        let idle_backup = setting!(flag, "idle.backup").unwrap_or(false);
+        if idle_backup && backup.is_none() {
+            l.error(format!(
+                "`{}` requires backups to be configured",
+                key("idle.backup").0
+            ));
+        }
+        let idle = idle_timeout.map(|timeout| IdleShutdown {
+            timeout,
+            grace_period: idle_grace_period,
+            backup: idle_backup,
+        });
+
+        // Listen on all interfaces on the port the server uses
+        let wake_address = setting!(get, "wake.address").unwrap_or_else(|| {
+            let port = address.rsplit_once(':').map_or("25565", |(_, port)| port);
+            format!("0.0.0.0:{port}")
+        });
+        let wake_motd =
+            setting!(get, "wake.motd").unwrap_or("Server sleeping — join to start".to_string());
+        let wake_kick_message = setting!(get, "wake.kick_message")
+            .unwrap_or("The server is starting, retry in a minute".to_string());
+        let wake = setting!(flag, "wake.enabled")
+            .unwrap_or(false)
+            .then(|| WakeConfig::new(wake_address, wake_motd, wake_kick_message));
+
+        let jobs_key = match &name {
+            Some(name) => format!("servers.{name}.jobs"),
+            None => "jobs".to_string(),
+        };
+        let jobs = l
+            .sections(&jobs_key)
+            .into_iter()
+            .filter_map(|job| {
+                let key = format!("{jobs_key}.{job}");
+                let cron = l.required::<String>(&format!("{key}.cron"), "");
+                let action = match l.required::<String>(&format!("{key}.action"), "")?.as_str() {
+                    "start" => Action::Start,
+                    "stop" => Action::Stop,
+                    "restart" => Action::Restart,
+                    "backup" => {
+                        if backup.is_none() {
+                            l.error(format!(
+                                "`{key}.action` `backup` requires backups to be configured"
+                            ));
+                        }
+                        Action::Backup
+                    }
+                    "say" => Action::Say(l.required(&format!("{key}.message"), "")?),
+                    "command" => Action::Command(l.required(&format!("{key}.command"), "")?),
+                    action => {
+                        l.error(format!(
+                            "`{key}.action`: `{action}` is not an action, use `start`, `stop`, `restart`, `backup`, `say` or `command`"
+                        ));
+                        return None;
+                    }
+                };
+                let cron = cron?;
+                match Job::new(job, cron.clone(), action) {
+                    Ok(job) => Some(job),
+                    Err(err) => {
+                        l.error(format!(
+                            "`{key}.cron` `{cron}` is not a valid cron expression: {err}"
+                        ));
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let (launch, server_args) = launch?;
+        Some(ServerProfile {
+            name: name.unwrap_or(DEFAULT_SERVER.to_string()),
+            console_channel_id: console_channel_id?,
+            chat_channel_id,
+            launch,
+            working_dir,
+            memory,
+            jvm_preset,
+            jvm_args,
+            server_args,
+            auto_accept_eula,
+            address,
+            status_poll_interval,
+            stop_timeout,
+            shutdown_timeout,
+            restart_countdown,
+            keep_running,
+            restart_policy,
+            rcon,
+            backup,
+            idle,
+            wake,
+            jobs,
+        })
+    }
+
+    /// The config the next server launch uses.
+    pub(crate) fn server_config(&self) -> ServerConfig {
+        ServerConfig::new(
+            self.launch.clone(),
+            self.working_dir.clone(),
+            self.memory,
+            self.jvm_preset,
+            self.jvm_args.clone(),
+            self.server_args.clone(),
+            self.auto_accept_eula,
+        )
+    }
+
+    pub(crate) fn manager_settings(&self) -> ManagerSettings {
+        ManagerSettings {
+            restart_policy: self.restart_policy,
+            stop_timeout: self.stop_timeout,
+            rcon: self.rcon.clone(),
+            backup: self.backup.clone(),
+            wake: self.wake.clone(),
+        }
+    }
+
+    fn entries(&self) -> Vec<(&'static str, String)> {
+        let (jar_path, java_path, command) = match &self.launch {
+            Launch::Jar { path, java_path } => {
+                (Some(path.display()), Some(java_path.display()), None)
+            }
+            Launch::Command(command) => (None, None, Some(args(command))),
+        };
+        let backup = self.backup.as_ref();
+        let retention = backup.and_then(BackupConfig::retention);
+        let keep = |keep: fn(&Retention) -> Option<u32>| optional(retention.and_then(keep));
+        let idle = self.idle.as_ref();
+        let wake = self.wake.as_ref();
+        vec![
+            ("console_channel_id", self.console_channel_id.to_string()),
+            ("chat_channel_id", optional(self.chat_channel_id)),
+            ("jar_path", optional(jar_path)),
+            ("java_path", optional(java_path)),
+            ("command", optional(command)),
+            ("working_dir", self.working_dir.display().to_string()),
+            (
+                "memory",
+                optional(self.memory.map(|memory| format!("{memory} MB"))),
+            ),
+            ("jvm_preset", self.jvm_preset.to_string()),
+            ("jvm_args", args(&self.jvm_args)),
+            ("server_args", args(&self.server_args)),
+            ("auto_accept_eula", self.auto_accept_eula.to_string()),
+            ("address", self.address.clone()),
+            ("status_poll_interval", secs(self.status_poll_interval)),
+            ("stop_timeout", secs(self.stop_timeout)),
+            ("shutdown_timeout", secs(self.shutdown_timeout)),
+            (
+                "restart_countdown",
+                list(self.restart_countdown.iter().map(|step| secs(*step))),
+            ),
+            ("keep_running", self.keep_running.to_string()),
+            (
+                "restart.max_crashes",
+                self.restart_policy.max_crashes().to_string(),
+            ),
+            ("restart.crash_window", secs(self.restart_policy.window())),
+            ("restart.backoff", secs(self.restart_policy.backoff())),
+            (
+                "rcon.address",
+                optional(self.rcon.as_ref().map(RconConfig::address)),
+            ),
+            (
+                "rcon.password",
+                optional(self.rcon.as_ref().map(RconConfig::password)),
+            ),
+            (
+                "backup.folder",
+                optional(backup.map(|backup| backup.folder().display())),
+            ),
+            (
+                "backup.server_folder",
+                optional(backup.map(|backup| backup.server_folder().display())),
+            ),
+            ("backup.name", optional(backup.map(BackupConfig::name))),
+            (
+                "backup.command",
+                optional(backup.and_then(BackupConfig::command).map(args)),
+            ),
+            ("backup.retention.keep_last", keep(|r| r.keep_last)),
+            ("backup.retention.keep_daily", keep(|r| r.keep_daily)),
+            ("backup.retention.keep_weekly", keep(|r| r.keep_weekly)),
+            ("backup.retention.keep_monthly", keep(|r| r.keep_monthly)),
+            (
+                "backup.retention.max_size",
+                optional(
+                    retention
+                        .and_then(|retention| retention.max_size)
+                        .map(|size| format!("{} MB", size / 1_000_000)),
+                ),
+            ),
+            (
+                "backup.retention.dry_run",
+                retention
+                    .is_some_and(|retention| retention.dry_run)
+                    .to_string(),
+            ),
+            (
+                "idle.timeout",
+                optional(idle.map(|idle| secs(idle.timeout))),
+            ),
+            (
+                "idle.grace_period",
+                optional(idle.map(|idle| secs(idle.grace_period))),
+            ),
+            (
+                "idle.backup",
+                idle.is_some_and(|idle| idle.backup).to_string(),
+            ),
+            ("wake.enabled", wake.is_some().to_string()),
+            ("wake.address", optional(wake.map(WakeConfig::address))),
+            ("wake.motd", optional(wake.map(WakeConfig::motd))),
+            (
+                "wake.kick_message",
+                optional(wake.map(WakeConfig::kick_message)),
+            ),
+            ("jobs", format!("{:?}", self.jobs)),
+        ]
+    }
+}
+
+/// Formats an optional setting for the change messages.
+fn optional(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| "not set".to_string(), |value| value.to_string())
+}
+
+/// Formats a list setting as comma separated values.
+fn list(values: impl IntoIterator<Item = impl fmt::Display>) -> String {
+    let values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+    if values.is_empty() {
+        "none".to_string()
+    } else {
+        values.join(", ")
+    }
+}
+
+/// Formats arguments like they're written in a shell, quoting the ones with spaces.
+fn args(args: &[String]) -> String {
+    if args.is_empty() {
+        return "none".to_string();
+    }
+    args.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("\"{arg}\"")
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn secs(duration: Duration) -> String {
+    format!("{}s", duration.as_secs())
+}
+
+fn describe_changes(
+    old: Vec<(&'static str, String)>,
+    new: Vec<(&'static str, String)>,
+    prefix: &str,
+) -> Vec<String> {
+    old.into_iter()
+        .zip(new)
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((key, old), (_, new))| {
+            // A backtick in a value would end the inline code
+            let show = |value: String| value.replace('`', "'");
+            let change = if SECRET_SETTINGS.contains(&key) || SUMMARIZED_SETTINGS.contains(&key) {
+                format!("{prefix}`{key}` changed")
+            } else {
+                format!("{prefix}`{key}`: `{}` → `{}`", show(old), show(new))
+            };
+            if LAUNCH_SETTINGS.contains(&key) {
+                format!("{change} (applies on the next `/start`)")
+            } else if RESTART_SETTINGS.contains(&key) {
+                format!("{change} (requires restarting EVE)")
+            } else {
+                change
+            }
+        })
+        .collect()
+}
+
+fn read_table(path: &Path) -> Result<Table, Vec<String>> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| vec![format!("Failed reading {}: {err}", path.display())])?;
+    content
+        .parse()
+        .map_err(|err| vec![format!("Failed parsing {}: {err}", path.display())])
+}
+
+/// Looks up an environment variable by its name.
+type EnvVars = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Reads settings from the config file or their environment variable, collecting every error on the way.
+struct Loader {
+    table: Table,
+    file: String,
+    var: EnvVars,
+    known: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl Loader {
+    fn error(&mut self, error: impl fmt::Display) {
+        self.errors.push(error.to_string());
+    }
+
+    /// Returns the environment variable if it's set, or the value of the dotted key in the file.
+    fn lookup(&mut self, key: &str, env: &str) -> Option<Source> {
+        self.known.insert(key.to_string());
+
+        if let Some(value) = (!env.is_empty())
+            .then(|| (self.var)(env))
+            .flatten()
+            .filter(|value| !value.is_empty())
+        {
+            return Some(Source::Env(value));
+        }
+
+        let (sections, field) = key.rsplit_once('.').expect("Config keys have a section");
+        let mut table = &self.table;
+        let mut path = String::new();
+        for section in sections.split('.') {
+            path = if path.is_empty() {
+                section.to_string()
+            } else {
+                format!("{path}.{section}")
+            };
+            match table.get(section) {
+                Some(Value::Table(section)) => table = section,
+                Some(_) => {
+                    self.error(format!("`{path}` in {} has to be a table", self.file));
+                    return None;
+                }
+                None => return None,
+            }
+        }
+        table.get(field).cloned().map(Source::File)
+    }
+
+    fn get<T: DeserializeOwned + FromStr>(&mut self, key: &str, env: &str) -> Option<T> {
+        match self.lookup(key, env)? {
+            Source::Env(value) => match value.trim().parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    self.error(format!("{env} env var has an invalid value `{value}`"));
+                    None
+                }
+            },
+            Source::File(value) => self.deserialize(key, value),
+        }
+    }
+
+    fn required<T: DeserializeOwned + FromStr>(&mut self, key: &str, env: &str) -> Option<T> {
+        let errors = self.errors.len();
+        let value = self.get(key, env);
+        // Don't report invalid values as missing as well
+        if value.is_none() && self.errors.len() == errors {
+            self.error(if env.is_empty() {
+                format!("`{key}` in {} is required", self.file)
+            } else {
+                format!("`{key}` in {} or the {env} env var is required", self.file)
+            });
+        }
+        value
+    }
+
+    /// Names of the tables in the section, like the jobs in `[jobs.<name>]`.
+    fn sections(&mut self, key: &str) -> Vec<String> {
+        let mut table = &self.table;
+        for section in key.split('.') {
+            match table.get(section) {
+                Some(Value::Table(section)) => table = section,
+                Some(_) => {
+                    let error = format!("`{key}` in {} has to be a table", self.file);
+                    self.error(error);
+                    // Don't report it as unknown as well
+                    self.known.insert(key.to_string());
+                    return Vec::new();
+                }
+                None => return Vec::new(),
+            }
+        }
+
+        // Anything else in the section is reported as an unknown key
+        table
+            .iter()
+            .filter(|(_, value)| matches!(value, Value::Table(_)))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Discord IDs can't be 0.
+    fn id<T>(&mut self, key: &str, env: &str) -> Option<Id<T>> {
+        let id = self.get::<u64>(key, env)?;
+        let id = Id::new_checked(id);
+        if id.is_none() {
+            self.error(format!("`{key}` can't be 0"));
+        }
+        id
+    }
+
+    fn required_id<T>(&mut self, key: &str, env: &str) -> Option<Id<T>> {
+        let id = self.required::<u64>(key, env)?;
+        let id = Id::new_checked(id);
+        if id.is_none() {
+            self.error(format!("`{key}` can't be 0"));
+        }
+        id
+    }
+
+    fn secs(&mut self, key: &str, env: &str) -> Option<Duration> {
+        self.get(key, env).map(Duration::from_secs)
+    }
+
+    /// Booleans in env vars are true if they're `1`, `true` or `t`.
+    fn flag(&mut self, key: &str, env: &str) -> Option<bool> {
+        match self.lookup(key, env)? {
+            Source::Env(v) => {
+                Some(v == "1" || v.to_lowercase() == "true" || v.to_lowercase() == "t")
+            }
+            Source::File(value) => self.deserialize(key, value),
+        }
+    }
+
+    /// Lists are comma separated in env vars.
+    fn list<T: DeserializeOwned + FromStr>(&mut self, key: &str, env: &str) -> Option<Vec<T>> {
+        match self.lookup(key, env)? {
+            Source::Env(value) => {
+                let mut list = Vec::new();
+                for item in value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                {
+                    match item.parse() {
+                        Ok(item) => list.push(item),
+                        Err(_) => {
+                            self.error(format!("{env} env var has an invalid value `{item}`"));
+                            return None;
+                        }
+                    }
+                }
+                Some(list)
+            }
+            Source::File(value) => self.deserialize(key, value),
+        }
+    }
+
+    /// Command line arguments are split like a shell does, or can be given as a list in the file.
+    fn args(&mut self, key: &str, env: &str) -> Option<Vec<String>> {
+        let value = match self.lookup(key, env)? {
+            Source::Env(value) => value,
+            Source::File(Value::String(value)) => value,
+            Source::File(value) => return self.deserialize(key, value),
+        };
+        match split_args(&value) {
+            Ok(args) => Some(args),
+            Err(err) => {
+                self.error(format!("`{key}` `{value}` {err}"));
+                None
+            }
+        }
+    }
+
+    /// Maps are tables in the file and comma separated `key=value` pairs in env vars.
+    fn map(&mut self, key: &str, env: &str) -> Option<Vec<(String, String)>> {
+        match self.lookup(key, env)? {
+            Source::Env(value) => {
+                let mut map = Vec::new();
+                for pair in value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|pair| !pair.is_empty())
+                {
+                    match pair.split_once('=') {
+                        Some((key, value)) => {
+                            map.push((key.trim().to_string(), value.trim().to_string()))
+                        }
+                        None => {
+                            self.error(format!(
+                                "{env} env var has to be a comma separated list of `key=value` pairs"
+                            ));
+                            return None;
+                        }
+                    }
+                }
+                Some(map)
+            }
+            Source::File(value) => self.deserialize::<Table>(key, value).map(|table| {
+                table
+                    .into_iter()
+                    .filter_map(|(key, value)| match value {
+                        Value::String(value) => Some((key, value)),
+                        _ => None,
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(&mut self, key: &str, value: Value) -> Option<T> {
+        match value.try_into() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.error(format!(
+                    "`{key}` in {}: {}",
+                    self.file,
+                    err.to_string().trim()
+                ));
+                None
+            }
+        }
+    }
+
+    /// Reports keys in the file EVE doesn't know, they're most likely typos.
+    fn check_unknown_keys(&mut self) {
+        let mut unknown = Vec::new();
+        self.unknown_keys(&self.table, "", &mut unknown);
+        for key in unknown {
+            self.error(format!("Unknown key `{key}` in {}", self.file));
+        }
+    }
+
+    fn unknown_keys(&self, table: &Table, prefix: &str, unknown: &mut Vec<String>) {
+        for (key, value) in table {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            if self.known.contains(&path) {
+                continue;
+            }
+            let section = format!("{path}.");
+            let known_section = self.known.iter().any(|known| known.starts_with(&section));
+            match value {
+                Value::Table(table) if known_section => self.unknown_keys(table, &path, unknown),
+                // Already reported while loading
+                _ if known_section => {}
+                _ => unknown.push(path),
+            }
+        }
+    }
+}
+
+enum Source {
+    Env(String),
+    File(Value),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Loads the TOML with the env vars, which replace the ones of the process.
+    fn load(toml: &str, vars: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+        let table = toml.parse().unwrap();
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::from_table(table, "eve.toml".to_string(), move |name| {
+            vars.get(name).cloned()
+        })
+    }
+
+    /// A config with everything that's required.
+    fn minimal(extra: &str) -> String {
+        format!(
+            r#"
+[discord]
+token = "file token"
+console_channel_id = 1
+
+[server]
+command = "./run.sh"
+working_dir = "{}"
+{extra}"#,
+            std::env::temp_dir().display()
+        )
+    }
+
+    #[test]
+    fn env_vars_override_the_file() {
+        let config = load(&minimal(""), &[("DISCORD_TOKEN", "env token")]).unwrap();
+        assert_eq!(config.discord.token, "env token");
+        let config = load(&minimal(""), &[("STOP_TIMEOUT", "5")]).unwrap();
+        assert_eq!(config.servers[0].stop_timeout, Duration::from_secs(5));
+
+        // Empty env vars count as not set
+        let config = load(&minimal(""), &[("DISCORD_TOKEN", "")]).unwrap();
+        assert_eq!(config.discord.token, "file token");
+
+        // Everything can be configured with env vars alone
+        let config = load(
+            "",
+            &[
+                ("DISCORD_TOKEN", "env token"),
+                ("CONSOLE_CHANNEL_ID", "1"),
+                ("SERVER_COMMAND", "./run.sh"),
+                (
+                    "SERVER_WORKING_DIR",
+                    &std::env::temp_dir().to_string_lossy(),
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.servers[0].name, DEFAULT_SERVER);
+    }
+
+    #[test]
+    fn profiles_ignore_env_vars() {
+        let toml = format!(
+            r#"
+[discord]
+token = "token"
+
+[servers.survival]
+console_channel_id = 1
+command = "./run.sh"
+working_dir = "{}"
+stop_timeout = 30
+"#,
+            std::env::temp_dir().display()
+        );
+        let config = load(&toml, &[("STOP_TIMEOUT", "5")]).unwrap();
+        assert_eq!(config.servers[0].name, "survival");
+        assert_eq!(config.servers[0].stop_timeout, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let toml = r#"
+[discord]
+console_channel_id = "one"
+
+[server]
+command = "./run.sh"
+stop_timeout = -1
+"#;
+        let errors = load(toml, &[("RESTART_MAX_CRASHES", "many")]).unwrap_err();
+        assert!(
+            errors.contains(
+                &"`discord.token` in eve.toml or the DISCORD_TOKEN env var is required".to_string()
+            ),
+            "{errors:?}"
+        );
+        assert!(errors
+            .iter()
+            .any(|error| error.contains("`discord.console_channel_id`")));
+        assert!(errors
+            .iter()
+            .any(|error| error.contains("`server.stop_timeout`")));
+        assert!(
+            errors.contains(&"RESTART_MAX_CRASHES env var has an invalid value `many`".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let errors = load(&minimal("comand = \"typo\"\n\n[unknown]\nkey = 1"), &[]).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "Unknown key `server.comand` in eve.toml".to_string(),
+                "Unknown key `unknown` in eve.toml".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_commands_without_program() {
+        let errors = load(
+            &minimal(""),
+            &[("SERVER_COMMAND", "{JVM_ARGS} -jar server.jar")],
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "`server.command` has to start with the program to run, not `{JVM_ARGS}`"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn describes_changes() {
+        let current = load(&minimal("stop_timeout = 60"), &[]).unwrap();
+        let new = load(
+            &minimal("stop_timeout = 30\nmemory = 4"),
+            &[("DISCORD_TOKEN", "new token")],
+        )
+        .unwrap();
+        assert_eq!(
+            current.changes(&new),
+            vec![
+                "`discord.token` changed (requires restarting EVE)".to_string(),
+                "default: `memory`: `not set` → `4 MB` (applies on the next `/start`)".to_string(),
+                "default: `stop_timeout`: `60s` → `30s`".to_string(),
+            ]
+        );
+        assert!(current.changes(&current).is_empty());
+
+        let temp_dir = std::env::temp_dir().display().to_string();
+        let backup = |command: &str| {
+            format!("[backup]\nfolder = \"{temp_dir}\"\nserver_folder = \"{temp_dir}\"\ncommand = \"{command}\"")
+        };
+        let current = load(&minimal(&backup("rclone --password old")), &[]).unwrap();
+        let new = load(
+            &minimal(&format!(
+                "{}\n[wake]\nenabled = true\nmotd = \"`Sleeping`\"\n[jobs.nightly]\ncron = \"0 4 * * *\"\naction = \"say\"\nmessage = \"`Hi`\"",
+                backup("rclone --password new")
+            )),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            current.changes(&new),
+            vec![
+                "default: `backup.command` changed".to_string(),
+                "default: `wake.enabled`: `false` → `true`".to_string(),
+                "default: `wake.address`: `not set` → `0.0.0.0:25565`".to_string(),
+                "default: `wake.motd`: `not set` → `'Sleeping'`".to_string(),
+                "default: `wake.kick_message`: `not set` → `The server is starting, retry in a minute`"
+                    .to_string(),
+                "default: `jobs` changed".to_string(),
+            ]
+        );
+    }
+}
@@ -1,16 +1,41 @@
-use crate::minecraft::{ServerCommand, ServerConfig, ServerManager, ServerStatus};
+use crate::audit::{AuditLog, Outcome};
+use crate::config::{Config, ServerProfile};
+use crate::minecraft::{
+    strip_formatting, Backup, ServerCommand, ServerEvent, ServerManager, ServerStatus,
+};
+use crate::schedule::{self, JobStates, LastRun, Skipped};
+use astrolabe::DateUtilities;
+use chrono::Utc;
+use chrono_tz::Tz;
 use log::{info, warn};
-use std::{env, sync::Arc};
-use tokio::sync::mpsc;
+use serde_json::json;
+use std::{collections::HashSet, fmt::Write, sync::Arc};
+use tokio::{
+    sync::{broadcast, mpsc, watch, RwLock},
+    time::{self, Duration},
+};
 use twilight_gateway::MessageSender;
-use twilight_http::{client::InteractionClient, Client};
+use twilight_http::{
+    client::InteractionClient, request::channel::reaction::RequestReactionType, Client,
+};
 use twilight_model::{
     application::{
-        command::CommandType,
-        interaction::{application_command::CommandOptionValue, InteractionData},
+        command::{CommandOptionChoice, CommandOptionChoiceValue, CommandType},
+        interaction::{
+            application_command::{CommandData, CommandDataOption, CommandOptionValue},
+            message_component::MessageComponentInteractionData,
+            InteractionData, InteractionType,
+        },
+    },
+    channel::message::{
+        component::{ActionRow, Button, ButtonStyle},
+        AllowedMentions, Component, MessageFlags,
     },
     gateway::{
-        payload::{incoming::InteractionCreate, outgoing::UpdatePresence},
+        payload::{
+            incoming::{InteractionCreate, MessageCreate},
+            outgoing::UpdatePresence,
+        },
         presence::{Activity, ActivityType, MinimalActivity, Status},
     },
     http::{
@@ -18,11 +43,14 @@ use twilight_model::{
         interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
     },
     id::{
-        marker::{ApplicationMarker, ChannelMarker, InteractionMarker},
+        marker::{ApplicationMarker, ChannelMarker, GuildMarker, InteractionMarker},
         Id,
     },
 };
-use twilight_util::builder::command::{CommandBuilder, StringBuilder};
+use twilight_util::builder::command::{CommandBuilder, StringBuilder, SubCommandBuilder};
+
+/// How long `/start` waits for the launch before recording it as failed in the audit log.
+const START_RESULT_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub(crate) async fn log_stdout(
     client: Arc<Client>,
@@ -30,26 +58,144 @@ pub(crate) async fn log_stdout(
     channel_id: Id<ChannelMarker>,
 ) -> anyhow::Result<()> {
     if content.chars().count() <= 2000 {
-        client.create_message(channel_id).content(&content)?.await?;
+        client
+            .create_message(channel_id)
+            .content(&content)?
+            .allowed_mentions(Some(&AllowedMentions::default()))
+            .await?;
     } else {
         let attachment = Attachment::from_bytes("console.log".to_string(), content.into_bytes(), 1);
         client
             .create_message(channel_id)
             .attachments(&[attachment])?
+            .allowed_mentions(Some(&AllowedMentions::default()))
             .await?;
     }
     Ok(())
 }
 
+/// A Minecraft server EVE manages, named after its profile in the config.
+pub(crate) struct Server {
+    pub(crate) name: String,
+    pub(crate) manager: Arc<ServerManager>,
+    pub(crate) cmd_sender: mpsc::Sender<ServerCommand>,
+    pub(crate) status: Arc<RwLock<ServerStatus>>,
+    pub(crate) jobs: RwLock<JobStates>,
+}
+
+/// Everything the Discord event handlers need.
+pub(crate) struct Context {
+    pub(crate) app_id: Id<ApplicationMarker>,
+    pub(crate) client: Arc<Client>,
+    pub(crate) servers: Arc<Vec<Server>>,
+    pub(crate) audit: Arc<AuditLog>,
+    pub(crate) config: watch::Receiver<Arc<Config>>,
+}
+
 pub(crate) async fn handle_interaction(
-    app_id: Id<ApplicationMarker>,
-    client: Arc<Client>,
-    server: Arc<ServerManager>,
-    cmd_sender: mpsc::Sender<ServerCommand>,
+    ctx: &Context,
     interaction: Box<InteractionCreate>,
 ) -> Result<(), anyhow::Error> {
+    let Context {
+        app_id,
+        client,
+        servers,
+        audit,
+        config,
+    } = ctx;
+    let config = config.borrow().clone();
+    let permissions = &config.permissions;
+
+    if let Some(InteractionData::MessageComponent(data)) = &interaction.data {
+        handle_component(ctx, &interaction, data, &config).await;
+        return Ok(());
+    }
+
     if let Some(InteractionData::ApplicationCommand(data)) = interaction.clone().0.data {
-        let interaction_client = client.interaction(app_id);
+        let interaction_client = client.interaction(*app_id);
+
+        if interaction.kind == InteractionType::ApplicationCommandAutocomplete {
+            suggest(&interaction_client, &interaction, &data, servers, &config).await;
+            return Ok(());
+        }
+
+        let author = interaction
+            .author()
+            .expect("Failed getting author of interaction");
+        let level = permissions.level(author.id, interaction.member.as_ref());
+        if level < permissions.required(&data.name) {
+            warn!(
+                "{} ({}) tried to use /{} without permission",
+                author.name, author.id, data.name
+            );
+            respond_ephemeral(
+                interaction_client,
+                interaction.id,
+                &interaction.token,
+                ":no_entry: You don't have permission to use this command".to_string(),
+            )
+            .await;
+            audit.record(author, &data, Outcome::Denied, None).await;
+            return Ok(());
+        }
+
+        let requested = string_option(&data, "server");
+        let target = match requested {
+            Some(name) => servers.iter().find(|server| server.name == name),
+            None if servers.len() == 1 => servers.first(),
+            None => None,
+        };
+        let Some(
+            target @ Server {
+                name,
+                manager: server,
+                cmd_sender,
+                status,
+                jobs,
+            },
+        ) = target
+        else {
+            let content = match requested {
+                Some(name) => format!(":warning: There's no server called `{name}`"),
+                // Without a server, /status shows all of them
+                None if data.name == "status" => {
+                    let mut content = String::new();
+                    for server in servers.iter() {
+                        let status = server.status.read().await;
+                        writeln!(content, "**{}**: {}", server.name, describe_status(&status))
+                            .unwrap_or_else(|err| warn!("Failed writing server status: {err}"));
+                    }
+                    respond_to_interaction(
+                        interaction_client,
+                        interaction.id,
+                        &interaction.token,
+                        content,
+                    )
+                    .await;
+                    audit.record(author, &data, Outcome::Success, None).await;
+                    return Ok(());
+                }
+                None => ":warning: Choose a server with the `server` option".to_string(),
+            };
+            respond_ephemeral(
+                interaction_client,
+                interaction.id,
+                &interaction.token,
+                content.clone(),
+            )
+            .await;
+            audit
+                .record(author, &data, Outcome::Failed, Some(content))
+                .await;
+            return Ok(());
+        };
+        let profile = config
+            .server(name)
+            .expect("Servers only change when EVE restarts");
+
+        let mut outcome = Outcome::Success;
+        let mut detail = None;
+
         match data.name.as_str() {
             "start" => {
                 if !server.running().await {
@@ -61,24 +207,18 @@ pub(crate) async fn handle_interaction(
                     )
                     .await;
 
-                    let server_path = env::var("SERVER_JAR_PATH").expect("");
-                    let memory = env::var("SERVER_MEMORY").expect("").parse().expect("");
-                    let jvm_flags = env::var("JVM_FLAGS").ok();
-                    let auto_accept_eula = env::var("AUTO_ACCEPT_EULA").map_or(false, |v| {
-                        v == "1" || v.to_lowercase() == "true" || v.to_lowercase() == "t"
-                    });
-
+                    let mut events = server.subscribe();
                     cmd_sender
                         .send(ServerCommand::StartServer {
-                            config: ServerConfig::new(
-                                server_path,
-                                memory,
-                                jvm_flags,
-                                auto_accept_eula,
-                            ),
+                            config: profile.server_config(),
                         })
                         .await
                         .expect("Failed sending value over sender");
+                    // The server is launched in the background, the audit log gets the result
+                    if let Err(error) = start_result(&mut events).await {
+                        outcome = Outcome::Failed;
+                        detail = Some(error);
+                    }
                 } else {
                     respond_to_interaction(
                         interaction_client,
@@ -87,6 +227,52 @@ pub(crate) async fn handle_interaction(
                         ":warning: Server already running".to_string(),
                     )
                     .await;
+                    outcome = Outcome::AlreadyRunning;
+                }
+            }
+            "status" => {
+                let content = describe_status(&*status.read().await);
+                respond_to_interaction(
+                    interaction_client,
+                    interaction.id,
+                    &interaction.token,
+                    content,
+                )
+                .await;
+            }
+            "restart" => {
+                if !server.running().await {
+                    respond_to_interaction(
+                        interaction_client,
+                        interaction.id,
+                        &interaction.token,
+                        ":warning: Server isn't running. Start it with `/start`".to_string(),
+                    )
+                    .await;
+                    outcome = Outcome::NotRunning;
+                } else {
+                    let countdown = profile.restart_countdown.clone();
+
+                    respond_to_interaction(
+                        interaction_client,
+                        interaction.id,
+                        &interaction.token,
+                        format!(
+                            ":arrows_counterclockwise: Restarting the server in {}s...",
+                            countdown
+                                .iter()
+                                .max()
+                                .copied()
+                                .unwrap_or_default()
+                                .as_secs()
+                        ),
+                    )
+                    .await;
+
+                    cmd_sender
+                        .send(ServerCommand::Restart { countdown })
+                        .await
+                        .expect("Failed sending value over sender");
                 }
             }
             "send" => {
@@ -98,23 +284,60 @@ pub(crate) async fn handle_interaction(
                         ":warning: Server isn't running. Start it with `/start`".to_string(),
                     )
                     .await;
+                    outcome = Outcome::NotRunning;
                 } else if let Some(cmd) = data
                     .options
+                    .clone()
                     .into_iter()
                     .find(|option| option.name == "command")
                 {
                     if let CommandOptionValue::String(cmd) = cmd.value {
-                        respond_to_interaction(
-                            interaction_client,
-                            interaction.id,
-                            &interaction.token,
-                            format!("`{cmd}`"),
-                        )
-                        .await;
-                        cmd_sender
-                            .send(ServerCommand::Stdin(cmd))
-                            .await
-                            .expect("Failed sending value over sender");
+                        if !permissions.allows_send(&cmd) {
+                            warn!(
+                                "{} ({}) tried to send blocked command `{cmd}`",
+                                author.name, author.id
+                            );
+                            respond_ephemeral(
+                                interaction_client,
+                                interaction.id,
+                                &interaction.token,
+                                format!(":no_entry: `{cmd}` isn't allowed"),
+                            )
+                            .await;
+                            outcome = Outcome::Blocked;
+                        } else if server.rcon_enabled() {
+                            defer_interaction(
+                                &interaction_client,
+                                interaction.id,
+                                &interaction.token,
+                            )
+                            .await;
+                            let content = match server.send_command(cmd.clone()).await {
+                                Ok(Some(output)) if !output.is_empty() => {
+                                    format!("`{cmd}`\n```\n{}\n```", format_rcon_output(&output))
+                                }
+                                Ok(_) => format!("`{cmd}`"),
+                                Err(err) => {
+                                    outcome = Outcome::Failed;
+                                    detail = Some(err.to_string());
+                                    format!("`{cmd}`\n:warning: RCON command failed: {err}")
+                                }
+                            };
+                            update_interaction(&interaction_client, &interaction.token, content)
+                                .await;
+                        } else {
+                            respond_to_interaction(
+                                interaction_client,
+                                interaction.id,
+                                &interaction.token,
+                                format!("`{cmd}`"),
+                            )
+                            .await;
+                            cmd_sender
+                                .send(ServerCommand::Stdin(cmd))
+                                .await
+                                .expect("Failed sending value over sender");
+                        }
                     }
                 }
             }
@@ -127,8 +350,10 @@ pub(crate) async fn handle_interaction(
                         ":warning: Server isn't running. Start it with `/start`".to_string(),
                     )
                     .await;
+                    outcome = Outcome::NotRunning;
                 } else if let Some(cmd) = data
                     .options
+                    .clone()
                     .into_iter()
                     .find(|option| option.name == "message")
                 {
@@ -153,12 +378,8 @@ pub(crate) async fn handle_interaction(
                             format!("<{user} Discord> {msg}"),
                         )
                         .await;
-                        let msg = format!(
-                            r##"tellraw @a ["",{{"text":"<{user} "}},{{"text":"Discord","color":"#5865F2"}},{{"text":">","color":"white"}},{{"text":" {msg}"}}]"##
-                        );
-
                         cmd_sender
-                            .send(ServerCommand::Stdin(msg))
+                            .send(ServerCommand::Stdin(tellraw(&user, &msg)))
                             .await
                             .expect("Failed sending value over sender");
                     }
@@ -173,6 +394,7 @@ pub(crate) async fn handle_interaction(
                         ":warning: Server isn't running. Start it with `/start`".to_string(),
                     )
                     .await;
+                    outcome = Outcome::NotRunning;
                 } else {
                     respond_to_interaction(
                         interaction_client,
@@ -183,7 +405,7 @@ pub(crate) async fn handle_interaction(
                     .await;
 
                     cmd_sender
-                        .send(ServerCommand::Stdin("stop".to_string()))
+                        .send(ServerCommand::Stop)
                         .await
                         .expect("Failed sending value over sender");
                 }
@@ -197,6 +419,7 @@ pub(crate) async fn handle_interaction(
                         ":warning: Server isn't running. Start it with `/start`".to_string(),
                     )
                     .await;
+                    outcome = Outcome::NotRunning;
                 } else {
                     respond_to_interaction(
                         interaction_client,
@@ -213,31 +436,659 @@ pub(crate) async fn handle_interaction(
                         .expect("Failed sending value over sender");
                 }
             }
+            "backups" => match server.backups().await {
+                Ok(backups) => {
+                    respond_to_interaction(
+                        interaction_client,
+                        interaction.id,
+                        &interaction.token,
+                        describe_backups(&backups),
+                    )
+                    .await;
+                }
+                Err(err) => {
+                    respond_ephemeral(
+                        interaction_client,
+                        interaction.id,
+                        &interaction.token,
+                        format!(":warning: Can't list the backups, {err}"),
+                    )
+                    .await;
+                    outcome = Outcome::Failed;
+                    detail = Some(err.to_string());
+                }
+            },
+            "restore" => {
+                let backup = string_option(&data, "backup").unwrap_or_default();
+                let custom_id = match server.backups().await {
+                    Ok(backups) if backups.iter().any(|listed| listed.name == backup) => {
+                        let custom_id = json!(["restore", name, backup]).to_string();
+                        // Discord allows at most 100 characters
+                        if custom_id.chars().count() <= 100 {
+                            Ok(custom_id)
+                        } else {
+                            Err(format!(
+                                ":warning: The name of `{backup}` is too long to restore it from Discord"
+                            ))
+                        }
+                    }
+                    Ok(_) => Err(format!(":warning: There's no backup called `{backup}`")),
+                    Err(err) => Err(format!(":warning: Can't list the backups, {err}")),
+                };
+
+                match custom_id {
+                    Ok(custom_id) => {
+                        ask_confirmation(
+                            interaction_client,
+                            interaction.id,
+                            &interaction.token,
+                            format!(
+                                ":warning: Restoring `{backup}` stops the server and replaces its worlds with the backup. The current worlds are moved into a snapshot in the backup folder."
+                            ),
+                            ("Restore", custom_id),
+                        )
+                        .await;
+                    }
+                    Err(content) => {
+                        respond_ephemeral(
+                            interaction_client,
+                            interaction.id,
+                            &interaction.token,
+                            content.clone(),
+                        )
+                        .await;
+                        outcome = Outcome::Failed;
+                        detail = Some(content);
+                    }
+                }
+            }
+            "schedule" => {
+                let subcommand = data
+                    .options
+                    .first()
+                    .map_or("", |option| option.name.as_str());
+                let job_name = string_option(&data, "job").unwrap_or_default();
+                let job = profile.jobs.iter().find(|job| job.name == job_name);
+                let content = match (subcommand, job) {
+                    ("list", _) => {
+                        Some(describe_jobs(profile, &*jobs.read().await, config.timezone))
+                    }
+                    (_, None) => None,
+                    ("pause", Some(job)) => Some(if jobs.write().await.pause(&job.name) {
+                        format!(
+                            ":pause_button: Paused `{}`, resume it with `/schedule resume`",
+                            job.name
+                        )
+                    } else {
+                        format!(":warning: `{}` is already paused", job.name)
+                    }),
+                    ("resume", Some(job)) => Some(if jobs.write().await.resume(&job.name) {
+                        format!(":arrow_forward: Resumed `{}`", job.name)
+                    } else {
+                        format!(":warning: `{}` isn't paused", job.name)
+                    }),
+                    (_, Some(job)) => Some(match schedule::run(job, target, profile).await {
+                        Ok(()) => format!(":alarm_clock: Running `{}` ({})", job.name, job.action),
+                        Err(skipped) => {
+                            outcome = match skipped {
+                                Skipped::AlreadyRunning => Outcome::AlreadyRunning,
+                                Skipped::NotRunning => Outcome::NotRunning,
+                            };
+                            format!(":warning: Can't run `{}`, {skipped}", job.name)
+                        }
+                    }),
+                };
+
+                match content {
+                    Some(content) => {
+                        respond_to_interaction(
+                            interaction_client,
+                            interaction.id,
+                            &interaction.token,
+                            content,
+                        )
+                        .await;
+                    }
+                    None => {
+                        let content = format!(":warning: There's no job called `{job_name}`");
+                        respond_ephemeral(
+                            interaction_client,
+                            interaction.id,
+                            &interaction.token,
+                            content.clone(),
+                        )
+                        .await;
+                        outcome = Outcome::Failed;
+                        detail = Some(content);
+                    }
+                }
+            }
             _ => {}
         };
+
+        audit.record(author, &data, outcome, detail).await;
+    }
+
+    Ok(())
+}
+
+/// Waits until the manager reports whether it launched the server, `Err` holds why it didn't.
+async fn start_result(events: &mut broadcast::Receiver<ServerEvent>) -> Result<(), String> {
+    let result = async {
+        loop {
+            match events.recv().await {
+                Ok(ServerEvent::Started) => return Ok(()),
+                Ok(ServerEvent::StartFailed { error }) => return Err(error),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err("the server manager stopped".to_string())
+                }
+            }
+        }
+    };
+    time::timeout(START_RESULT_TIMEOUT, result)
+        .await
+        .unwrap_or_else(|_| Err("the server didn't report whether it started".to_string()))
+}
+
+fn describe_status(status: &ServerStatus) -> String {
+    match status {
+        ServerStatus::Offline => ":red_circle: Server is offline".to_string(),
+        ServerStatus::Starting => ":orange_circle: Server is starting".to_string(),
+        ServerStatus::Stopping => ":orange_circle: Server is stopping".to_string(),
+        ServerStatus::Running { info: None } => ":green_circle: Server is online".to_string(),
+        ServerStatus::Running { info: Some(info) } => format!(
+            ":green_circle: Server is online\n**Players:** {}/{}\n**Version:** {}\n**MOTD:** {}",
+            info.players, info.max_players, info.version, info.motd
+        ),
+    }
+}
+
+/// Lists the jobs of the server. Times are Discord timestamps, which show in the timezone of the reader.
+fn describe_jobs(profile: &ServerProfile, states: &JobStates, timezone: Tz) -> String {
+    if profile.jobs.is_empty() {
+        return ":calendar: No jobs are scheduled".to_string();
+    }
+
+    let now = Utc::now().with_timezone(&timezone);
+    let mut content = format!(":calendar: **Scheduled jobs** (cron times in `{timezone}`)");
+    for job in &profile.jobs {
+        let mut line = format!("\n- `{}`: {} at `{}`", job.name, job.action, job.cron);
+        if states.paused(&job.name) {
+            line.push_str(", **paused**");
+        } else if let Some(next) = job.next_run(&now) {
+            line.push_str(&format!(", next <t:{}:R>", next.timestamp()));
+        }
+        match states.last_run(&job.name) {
+            Some(LastRun { at, result: Ok(()) }) => {
+                line.push_str(&format!(", last ran <t:{}:R>", at.timestamp()));
+            }
+            Some(LastRun {
+                at,
+                result: Err(skipped),
+            }) => {
+                line.push_str(&format!(
+                    ", last skipped <t:{}:R> because {skipped}",
+                    at.timestamp()
+                ));
+            }
+            None => {}
+        }
+        content.push_str(&line);
+    }
+    content
+}
+
+/// Lists the newest backups with their size, the date is a Discord timestamp.
+fn describe_backups(backups: &[Backup]) -> String {
+    const SHOWN: usize = 20;
+    if backups.is_empty() {
+        return ":file_cabinet: There are no backups yet".to_string();
+    }
+
+    let total = backups.iter().map(|backup| backup.size).sum();
+    let mut content = format!(
+        ":file_cabinet: **{} backups** ({})",
+        backups.len(),
+        format_size(total)
+    );
+    for backup in backups.iter().take(SHOWN) {
+        content.push_str(&format!(
+            "\n- `{}` from <t:{}:f>, {}",
+            backup.name,
+            backup.created.timestamp(),
+            format_size(backup.size)
+        ));
+    }
+    if backups.len() > SHOWN {
+        content.push_str(&format!("\n…and {} older backups", backups.len() - SHOWN));
+    }
+    content
+}
+
+/// Handles the buttons of confirmation prompts. Their custom ID is a JSON array of the command and its
+/// arguments, the cancel button's is `["cancel"]`.
+async fn handle_component(
+    ctx: &Context,
+    interaction: &InteractionCreate,
+    data: &MessageComponentInteractionData,
+    config: &Config,
+) {
+    let interaction_client = ctx.client.interaction(ctx.app_id);
+    let author = interaction
+        .author()
+        .expect("Failed getting author of interaction");
+    let arguments: Vec<String> = serde_json::from_str(&data.custom_id).unwrap_or_default();
+    let (command, server_name, backup) = match arguments.as_slice() {
+        [command, server_name, backup] if command == "restore" => (command, server_name, backup),
+        [command] if command == "cancel" => {
+            update_message(
+                &interaction_client,
+                interaction.id,
+                &interaction.token,
+                ":x: Cancelled".to_string(),
+            )
+            .await;
+            return;
+        }
+        // Buttons of older versions of EVE
+        _ => {
+            warn!("Unknown button {}", data.custom_id);
+            respond_ephemeral(
+                interaction_client,
+                interaction.id,
+                &interaction.token,
+                ":warning: This button isn't known anymore, run the command again".to_string(),
+            )
+            .await;
+            return;
+        }
+    };
+    let arguments = [
+        ("backup", backup.as_str()),
+        ("server", server_name.as_str()),
+    ];
+
+    let permissions = &config.permissions;
+    if permissions.level(author.id, interaction.member.as_ref()) < permissions.required(command) {
+        update_message(
+            &interaction_client,
+            interaction.id,
+            &interaction.token,
+            ":no_entry: You don't have permission to use this command".to_string(),
+        )
+        .await;
+        ctx.audit
+            .record_confirmation(author, command, &arguments, Outcome::Denied, None)
+            .await;
+        return;
+    }
+    // The button might be from before EVE restarted with a different config
+    let Some(server) = ctx
+        .servers
+        .iter()
+        .find(|server| &server.name == server_name)
+    else {
+        let content = format!(":warning: There's no server called `{server_name}`");
+        respond_ephemeral(
+            interaction_client,
+            interaction.id,
+            &interaction.token,
+            content.clone(),
+        )
+        .await;
+        ctx.audit
+            .record_confirmation(author, command, &arguments, Outcome::Failed, Some(content))
+            .await;
+        return;
+    };
+
+    update_message(
+        &interaction_client,
+        interaction.id,
+        &interaction.token,
+        format!(":rewind: Restoring `{backup}`, the progress is posted in the console channel"),
+    )
+    .await;
+    server
+        .cmd_sender
+        .send(ServerCommand::Restore {
+            backup: backup.clone(),
+        })
+        .await
+        .expect("Failed sending value over sender");
+    ctx.audit
+        .record_confirmation(author, command, &arguments, Outcome::Success, None)
+        .await;
+}
+
+/// Options of the command, or of its subcommand if it has one.
+fn command_options(data: &CommandData) -> &[CommandDataOption] {
+    match data.options.first().map(|option| &option.value) {
+        Some(CommandOptionValue::SubCommand(options)) => options,
+        _ => &data.options,
+    }
+}
+
+fn string_option<'a>(data: &'a CommandData, name: &str) -> Option<&'a str> {
+    command_options(data)
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| match &option.value {
+            CommandOptionValue::String(value) => Some(value.as_str()),
+            _ => None,
+        })
+}
+
+/// Suggests the servers, or the jobs for the `job` option and the backups for the `backup` option, whose
+/// name contains what the user typed so far.
+async fn suggest(
+    interaction_client: &InteractionClient<'_>,
+    interaction: &InteractionCreate,
+    data: &CommandData,
+    servers: &[Server],
+    config: &Config,
+) {
+    let (option, typed) = command_options(data)
+        .iter()
+        .find_map(|option| match &option.value {
+            CommandOptionValue::Focused(typed, _) => {
+                Some((option.name.as_str(), typed.to_lowercase()))
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+    let backups;
+    let names: Vec<&str> = if option == "job" {
+        // Jobs of the chosen server, or of every server until one is chosen
+        let requested = string_option(data, "server");
+        config
+            .servers
+            .iter()
+            .filter(|profile| requested.is_none_or(|name| profile.name == name))
+            .flat_map(|profile| profile.jobs.iter().map(|job| job.name.as_str()))
+            .collect()
+    } else if option == "backup" {
+        // Backups of the chosen server, or of the only one
+        let requested = string_option(data, "server");
+        let server = match requested {
+            Some(name) => servers.iter().find(|server| server.name == name),
+            None if servers.len() == 1 => servers.first(),
+            None => None,
+        };
+        backups = match server {
+            Some(server) => server.manager.backups().await.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        backups.iter().map(|backup| backup.name.as_str()).collect()
+    } else {
+        servers.iter().map(|server| server.name.as_str()).collect()
+    };
+    let mut seen = HashSet::new();
+    let choices = names
+        .into_iter()
+        .filter(|name| name.to_lowercase().contains(&typed) && seen.insert(*name))
+        // Discord shows at most 25 choices
+        .take(25)
+        .map(|name| CommandOptionChoice {
+            name: name.to_string(),
+            name_localizations: None,
+            value: CommandOptionChoiceValue::String(name.to_string()),
+        })
+        .collect();
+
+    let result = interaction_client
+        .create_response(
+            interaction.id,
+            &interaction.token,
+            &InteractionResponse {
+                kind: InteractionResponseType::ApplicationCommandAutocompleteResult,
+                data: Some(InteractionResponseData {
+                    choices: Some(choices),
+                    ..Default::default()
+                }),
+            },
+        )
+        .await;
+    if let Err(e) = result {
+        warn!("Failed responding to autocomplete: {e}");
+    }
+}
+
+/// Relays messages from the chat channel into the ingame chat and messages from the console channel into
+/// the server's console.
+pub(crate) async fn handle_message(ctx: &Context, message: Box<MessageCreate>) {
+    // Ignores EVE's own console output as well
+    if message.author.bot {
+        return;
+    }
+
+    let config = ctx.config.borrow().clone();
+    for profile in &config.servers {
+        let server = ctx
+            .servers
+            .iter()
+            .find(|server| server.name == profile.name)
+            .expect("Servers only change when EVE restarts");
+        if !config.discord.console_input_allowed.is_empty()
+            && message.channel_id == profile.console_channel_id
+        {
+            handle_console_message(ctx, server, message).await;
+            return;
+        } else if profile.chat_channel_id == Some(message.channel_id) {
+            if server.manager.running().await {
+                relay_chat_message(&server.cmd_sender, message).await;
+            }
+            return;
+        }
+    }
+}
+
+/// Sends every line of the message to the server's console and reacts with the outcome.
+async fn handle_console_message(ctx: &Context, server: &Server, message: Box<MessageCreate>) {
+    let Context {
+        client,
+        audit,
+        config,
+        ..
+    } = ctx;
+    let config = config.borrow().clone();
+    let permissions = &config.permissions;
+
+    let allowed = &config.discord.console_input_allowed;
+    let authorized = allowed.contains(&message.author.id.get())
+        || message.member.as_ref().is_some_and(|member| {
+            member
+                .roles
+                .iter()
+                .any(|role| allowed.contains(&role.get()))
+        });
+    if !authorized {
+        return;
+    }
+
+    // The allowlist only restricts who can type into the console, it grants nothing beyond `/send`
+    let level = permissions.level(message.author.id, message.member.as_ref());
+    if level < permissions.required("send") {
+        warn!(
+            "{} ({}) tried to use the console without permission",
+            message.author.name, message.author.id
+        );
+        audit
+            .record_console(&message.author, &message.content, Outcome::Denied, None)
+            .await;
+        react(client, &message, "❌").await;
+        return;
+    }
+
+    let mut result = Ok(());
+    for line in message
+        .content
+        .trim_matches('`')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if !permissions.allows_send(line) {
+            warn!(
+                "{} ({}) tried to send blocked command `{line}`",
+                message.author.name, message.author.id
+            );
+            audit
+                .record_console(&message.author, line, Outcome::Blocked, None)
+                .await;
+            result = Err(format!("`{line}` isn't allowed"));
+            break;
+        }
+        info!(
+            "{} sent `{line}` to the console of {}",
+            message.author.name, server.name
+        );
+        result = server
+            .manager
+            .send_stdin(line.to_string())
+            .await
+            .map_err(|err| err.to_string());
+        let (outcome, detail) = match &result {
+            Ok(()) => (Outcome::Success, None),
+            Err(err) => (Outcome::Failed, Some(err.clone())),
+        };
+        audit
+            .record_console(&message.author, line, outcome, detail)
+            .await;
+        if result.is_err() {
+            break;
+        }
     }
 
+    let emoji = match result {
+        Ok(()) => "✅",
+        Err(err) => {
+            warn!("Failed sending console message to the server: {err}");
+            "❌"
+        }
+    };
+    react(client, &message, emoji).await;
+}
+
+/// Shows if a console message was delivered.
+async fn react(client: &Client, message: &MessageCreate, emoji: &str) {
+    client
+        .create_reaction(
+            message.channel_id,
+            message.id,
+            &RequestReactionType::Unicode { name: emoji },
+        )
+        .await
+        .map(|_| ())
+        .unwrap_or_else(|err| warn!("Failed reacting to console message: {err}"));
+}
+
+async fn relay_chat_message(cmd_sender: &mpsc::Sender<ServerCommand>, message: Box<MessageCreate>) {
+    let mut content = message.content.replace('\n', " ");
+    for mention in &message.mentions {
+        content = content
+            .replace(&format!("<@{}>", mention.id), &format!("@{}", mention.name))
+            .replace(
+                &format!("<@!{}>", mention.id),
+                &format!("@{}", mention.name),
+            );
+    }
+    for attachment in &message.attachments {
+        write!(content, " [{}]", attachment.filename)
+            .unwrap_or_else(|err| warn!("Failed writing attachment name: {err}"));
+    }
+    let content = content.trim();
+    if content.is_empty() {
+        return;
+    }
+
+    cmd_sender
+        .send(ServerCommand::Stdin(tellraw(&message.author.name, content)))
+        .await
+        .expect("Failed sending value over sender");
+}
+
+/// Builds the `tellraw` command which shows a message from Discord in the ingame chat.
+fn tellraw(user: &str, msg: &str) -> String {
+    let components = json!([
+        "",
+        { "text": format!("<{user} ") },
+        { "text": "Discord", "color": "#5865F2" },
+        { "text": ">", "color": "white" },
+        { "text": format!(" {msg}") },
+    ]);
+    format!("tellraw @a {components}")
+}
+
+/// Posts a message into the channel without pinging anyone.
+pub(crate) async fn post_message(
+    client: &Client,
+    content: &str,
+    channel_id: Id<ChannelMarker>,
+) -> anyhow::Result<()> {
+    client
+        .create_message(channel_id)
+        .content(content)?
+        .allowed_mentions(Some(&AllowedMentions::default()))
+        .await?;
     Ok(())
 }
 
 pub(crate) async fn set_commands(
     app_id: Id<ApplicationMarker>,
     client: Arc<Client>,
+    dev_guild_id: Option<Id<GuildMarker>>,
 ) -> Result<(), anyhow::Error> {
+    let server_option = || {
+        StringBuilder::new(
+            "server",
+            "Server to use, can be left out if there's only one.",
+        )
+        .autocomplete(true)
+    };
+    let job_option = || {
+        StringBuilder::new("job", "Name of the job.")
+            .required(true)
+            .autocomplete(true)
+    };
+    let backup_option = || {
+        StringBuilder::new("backup", "Name of the backup.")
+            .required(true)
+            .autocomplete(true)
+    };
     let commands = [
         CommandBuilder::new(
             "start",
             "Starts the Minecraft server",
             CommandType::ChatInput,
         )
+        .option(server_option())
+        .build(),
+        CommandBuilder::new("stop", "Stops the Minecraft server", CommandType::ChatInput)
+            .option(server_option())
+            .build(),
+        CommandBuilder::new(
+            "status",
+            "Shows the status of the Minecraft server",
+            CommandType::ChatInput,
+        )
+        .option(server_option())
+        .build(),
+        CommandBuilder::new(
+            "restart",
+            "Restarts the Minecraft server after an ingame countdown",
+            CommandType::ChatInput,
+        )
+        .option(server_option())
         .build(),
-        CommandBuilder::new("stop", "Stops the Minecraft server", CommandType::ChatInput).build(),
         CommandBuilder::new(
             "send",
             "Pass a command to the Minecraft server.",
             CommandType::ChatInput,
         )
         .option(StringBuilder::new("command", "Command to pass to the server.").required(true))
+        .option(server_option())
         .build(),
         CommandBuilder::new(
             "say",
@@ -245,23 +1096,60 @@ pub(crate) async fn set_commands(
             CommandType::ChatInput,
         )
         .option(StringBuilder::new("message", "Message to pass to the ingame chat.").required(true))
+        .option(server_option())
         .build(),
         CommandBuilder::new(
             "backup",
             "Creates a backup of the Minecraft server",
             CommandType::ChatInput,
         )
+        .option(server_option())
+        .build(),
+        CommandBuilder::new(
+            "backups",
+            "Lists the backups of the Minecraft server",
+            CommandType::ChatInput,
+        )
+        .option(server_option())
+        .build(),
+        CommandBuilder::new(
+            "restore",
+            "Replaces the worlds with a backup, stopping the server while it's restored",
+            CommandType::ChatInput,
+        )
+        .option(backup_option())
+        .option(server_option())
+        .build(),
+        CommandBuilder::new(
+            "schedule",
+            "Shows and controls the scheduled jobs",
+            CommandType::ChatInput,
+        )
+        .option(
+            SubCommandBuilder::new("list", "Lists the jobs and when they run next")
+                .option(server_option()),
+        )
+        .option(
+            SubCommandBuilder::new("pause", "Pauses a job until it's resumed")
+                .option(job_option())
+                .option(server_option()),
+        )
+        .option(
+            SubCommandBuilder::new("resume", "Resumes a paused job")
+                .option(job_option())
+                .option(server_option()),
+        )
+        .option(
+            SubCommandBuilder::new("run", "Runs a job now")
+                .option(job_option())
+                .option(server_option()),
+        )
         .build(),
     ];
 
     let interaction_client = client.interaction(app_id);
 
-    if env::var("DEV").is_ok() {
-        let guild_id = Id::new(
-            env::var("GUILD_ID")
-                .expect("GUILD_ID env var not found")
-                .parse()?,
-        );
+    if let Some(guild_id) = dev_guild_id {
         interaction_client
             .set_guild_commands(guild_id, &commands)
             .await?;
@@ -298,123 +1186,447 @@ async fn respond_to_interaction(
     }
 }
 
-pub(crate) async fn manage_status(
-    discord_msg_sender: &MessageSender,
-    current_status: ServerStatus,
-    max_players: Option<u8>,
-    msg: &str,
-) -> ServerStatus {
-    if current_status == ServerStatus::Offline {
-        set_status(discord_msg_sender, ServerStatus::Starting).await;
-        return ServerStatus::Starting;
+async fn respond_ephemeral(
+    interaction_client: InteractionClient<'_>,
+    id: Id<InteractionMarker>,
+    token: &str,
+    content: String,
+) {
+    let result = interaction_client
+        .create_response(
+            id,
+            token,
+            &InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(InteractionResponseData {
+                    content: Some(content),
+                    flags: Some(MessageFlags::EPHEMERAL),
+                    ..Default::default()
+                }),
+            },
+        )
+        .await;
+    if let Err(e) = result {
+        warn!("Failed responding to interaction: {e}");
+    }
+}
+
+/// Asks the user to confirm with a button, which only they can see.
+async fn ask_confirmation(
+    interaction_client: InteractionClient<'_>,
+    id: Id<InteractionMarker>,
+    token: &str,
+    content: String,
+    (label, custom_id): (&str, String),
+) {
+    let button = |label: &str, custom_id: String, style| {
+        Component::Button(Button {
+            custom_id: Some(custom_id),
+            disabled: false,
+            emoji: None,
+            label: Some(label.to_string()),
+            style,
+            url: None,
+        })
     };
-    if msg.contains("! For help, type \"help\"") {
-        set_status(
-            discord_msg_sender,
-            ServerStatus::Running {
-                players: 0,
-                max_players,
+    let result = interaction_client
+        .create_response(
+            id,
+            token,
+            &InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(InteractionResponseData {
+                    content: Some(content),
+                    flags: Some(MessageFlags::EPHEMERAL),
+                    components: Some(vec![Component::ActionRow(ActionRow {
+                        components: vec![
+                            button(label, custom_id, ButtonStyle::Danger),
+                            button(
+                                "Cancel",
+                                json!(["cancel"]).to_string(),
+                                ButtonStyle::Secondary,
+                            ),
+                        ],
+                    })]),
+                    ..Default::default()
+                }),
             },
         )
         .await;
-        return ServerStatus::Running {
-            players: 0,
-            max_players,
-        };
+    if let Err(e) = result {
+        warn!("Failed responding to interaction: {e}");
+    }
+}
+
+/// Replaces the message with the buttons, removing them.
+async fn update_message(
+    interaction_client: &InteractionClient<'_>,
+    id: Id<InteractionMarker>,
+    token: &str,
+    content: String,
+) {
+    let result = interaction_client
+        .create_response(
+            id,
+            token,
+            &InteractionResponse {
+                kind: InteractionResponseType::UpdateMessage,
+                data: Some(InteractionResponseData {
+                    content: Some(content),
+                    components: Some(Vec::new()),
+                    ..Default::default()
+                }),
+            },
+        )
+        .await;
+    if let Err(e) = result {
+        warn!("Failed updating message: {e}");
     }
-    if let ServerStatus::Running {
-        players,
-        max_players,
-    } = current_status
+}
+
+async fn defer_interaction(
+    interaction_client: &InteractionClient<'_>,
+    id: Id<InteractionMarker>,
+    token: &str,
+) {
+    let result = interaction_client
+        .create_response(
+            id,
+            token,
+            &InteractionResponse {
+                kind: InteractionResponseType::DeferredChannelMessageWithSource,
+                data: None,
+            },
+        )
+        .await;
+    if let Err(e) = result {
+        warn!("Failed deferring interaction: {e}");
+    }
+}
+
+async fn update_interaction(
+    interaction_client: &InteractionClient<'_>,
+    token: &str,
+    content: String,
+) {
+    let result = match interaction_client
+        .update_response(token)
+        .content(Some(&content))
     {
-        if msg.contains("logged in with entity id") && max_players.is_some() {
-            set_status(
-                discord_msg_sender,
-                ServerStatus::Running {
-                    players: players + 1,
-                    max_players,
-                },
-            )
-            .await;
-            return ServerStatus::Running {
-                players: players + 1,
-                max_players,
+        Ok(request) => request.await.map(|_| ()).map_err(anyhow::Error::from),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
+        warn!("Failed updating interaction response: {e}");
+    }
+}
+
+/// Strips Minecraft's `§` formatting codes and shortens the output to fit into a Discord message.
+fn format_rcon_output(output: &str) -> String {
+    let formatted = strip_formatting(output);
+    if formatted.chars().count() > 1800 {
+        return formatted.chars().take(1800).collect::<String>() + "…";
+    }
+    formatted
+}
+
+/// Formats a number of bytes like `1.5 GB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Renders an event as a message for the console channel.
+pub(crate) fn render_event(event: &ServerEvent) -> Option<String> {
+    match event {
+        ServerEvent::ConsoleLine(line) => Some(line.clone()),
+        ServerEvent::StartFailed { error } => Some(format!("Failed to start server: {error}")),
+        ServerEvent::EulaAccepted => Some(":green_circle: Accepting eula".to_string()),
+        ServerEvent::Launching {
+            preset,
+            command_line,
+        } => Some(format!(
+            ":rocket: Launching the server with the `{preset}` JVM preset\n```\n{command_line}\n```"
+        )),
+        ServerEvent::Attached {
+            pid,
+            commands_available,
+        } => {
+            let mut msg = match pid {
+                Some(pid) => format!(":link: Attached to running server (PID {pid})"),
+                None => ":link: Attached to running server".to_string(),
             };
+            if !commands_available {
+                msg.push_str(
+                    "\n:warning: Commands can't be sent to an attached server, configure RCON to control it",
+                );
+            }
+            Some(msg)
         }
-        if msg.contains(" left the game") && max_players.is_some() {
-            set_status(
-                discord_msg_sender,
-                ServerStatus::Running {
-                    players: players - 1,
-                    max_players,
-                },
-            )
-            .await;
-            return ServerStatus::Running {
-                players: players - 1,
-                max_players,
-            };
+        ServerEvent::StopEscalated {
+            signal,
+            grace_period,
+        } => Some(format!(
+            ":warning: Server didn't stop within {}s, sending `{signal}`",
+            grace_period.as_secs()
+        )),
+        ServerEvent::StopFailed => {
+            Some(":rotating_light: Server is still running after `SIGKILL`".to_string())
         }
+        ServerEvent::Stopped {
+            exit_status: Some(exit_status),
+        } if !exit_status.success() => {
+            Some(format!(":red_circle: Server stopped ({exit_status})"))
+        }
+        ServerEvent::Stopped { .. } => Some(":red_circle: Server stopped".to_string()),
+        ServerEvent::Crashed {
+            exit_status,
+            restart: Some(restart),
+        } => Some(format!(
+            ":warning: Server crashed ({exit_status}). Restarting in {}s (attempt {}/{})",
+            restart.delay.as_secs(),
+            restart.attempt,
+            restart.max_crashes
+        )),
+        ServerEvent::Crashed {
+            exit_status,
+            restart: None,
+        } => Some(format!(":warning: Server crashed ({exit_status})")),
+        ServerEvent::RestartsExhausted {
+            exit_status,
+            crashes,
+            window,
+        } => Some(format!(
+            ":rotating_light: Server crashed ({exit_status}) {crashes} times within {} minutes. Giving up on automatic restarts, start it manually with `/start`",
+            window.as_secs() / 60
+        )),
+        ServerEvent::WakeRequested { player } => Some(format!(
+            ":alarm_clock: {} tried to join, starting the server",
+            escape_markdown(player)
+        )),
+        ServerEvent::BackupProgress { bytes, total_bytes } => Some(format!(
+            ":file_cabinet: Backup {}% done ({} of {})",
+            (bytes * 100).checked_div(*total_bytes).unwrap_or(100),
+            format_size(*bytes),
+            format_size(*total_bytes)
+        )),
+        ServerEvent::BackupFinished {
+            name,
+            size: Some(size),
+        } => Some(format!(
+            ":white_check_mark: Successfully created server backup `{name}` ({})",
+            format_size(*size)
+        )),
+        ServerEvent::BackupFinished { name, size: None } => Some(format!(
+            ":white_check_mark: Successfully created server backup `{name}`"
+        )),
+        ServerEvent::BackupFailed { reason } => Some(format!(
+            ":warning: Failed creating server backup, {reason}"
+        )),
+        ServerEvent::BackupsPruned {
+            deleted,
+            freed,
+            dry_run,
+        } => {
+            let names = deleted
+                .iter()
+                .map(|name| format!("`{name}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some(if *dry_run {
+                format!(
+                    ":wastebasket: Retention dry run, would delete {} old backups ({}): {names}",
+                    deleted.len(),
+                    format_size(*freed)
+                )
+            } else {
+                format!(
+                    ":wastebasket: Deleted {} old backups ({}): {names}",
+                    deleted.len(),
+                    format_size(*freed)
+                )
+            })
+        }
+        ServerEvent::PruneFailed { reason } => Some(format!(
+            ":warning: Failed deleting old backups, {reason}"
+        )),
+        ServerEvent::RestoreStarted { name } => {
+            Some(format!(":rewind: Restoring server backup `{name}`..."))
+        }
+        ServerEvent::RestoreFinished { name, snapshot } => Some(format!(
+            ":white_check_mark: Restored server backup `{name}`, the previous worlds are in `{}`",
+            snapshot.display()
+        )),
+        ServerEvent::RestoreFailed { reason } => Some(format!(
+            ":warning: Failed restoring server backup, {reason}"
+        )),
+        ServerEvent::Started
+        | ServerEvent::Ready { .. }
+        | ServerEvent::Stopping
+        | ServerEvent::PlayerJoined { .. }
+        | ServerEvent::PlayerLeft { .. }
+        | ServerEvent::Chat { .. }
+        | ServerEvent::Death { .. }
+        | ServerEvent::Advancement { .. }
+        | ServerEvent::Lagging { .. } => None,
     }
-    if msg.contains("Stopping the server") {
-        set_status(discord_msg_sender, ServerStatus::Stopping).await;
-        return ServerStatus::Stopping;
-    }
-    if msg.contains(":red_circle: Server stopped") {
-        set_status(discord_msg_sender, ServerStatus::Offline).await;
-        return ServerStatus::Offline;
+}
+
+/// Renders an event as a message for the chat channel. Only game events players care about are rendered.
+pub(crate) fn render_chat_event(event: &ServerEvent) -> Option<String> {
+    match event {
+        ServerEvent::Chat { player, message } => Some(format!(
+            "**{}**: {}",
+            escape_markdown(player),
+            escape_markdown(message)
+        )),
+        ServerEvent::PlayerJoined { player } => Some(format!(
+            ":arrow_right: **{}** joined the game",
+            escape_markdown(player)
+        )),
+        ServerEvent::PlayerLeft { player } => Some(format!(
+            ":arrow_left: **{}** left the game",
+            escape_markdown(player)
+        )),
+        ServerEvent::Death { message, .. } => Some(format!(":skull: {}", escape_markdown(message))),
+        ServerEvent::Advancement {
+            player,
+            advancement,
+        } => Some(format!(
+            ":trophy: **{}** has made the advancement **{}**",
+            escape_markdown(player),
+            escape_markdown(advancement)
+        )),
+        _ => None,
     }
-    current_status
 }
 
-pub(crate) async fn set_status(discord_msg_sender: &MessageSender, status: ServerStatus) {
-    let request = match status {
-        ServerStatus::Offline => {
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Playing,
-                name: "🔴 Offline".to_owned(),
-                url: None,
-            });
-            UpdatePresence::new(Vec::from([activity]), false, None, Status::Idle)
-                .expect("Failed creating UpdatePresence payload")
+pub(crate) fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '#' | '-' | '[' | ']'
+        ) {
+            escaped.push('\\');
         }
-        ServerStatus::Starting => {
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Playing,
-                name: "🟠 Starting".to_owned(),
-                url: None,
-            });
-            UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)
-                .expect("Failed creating UpdatePresence payload")
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Returns the status the server is in after the event.
+pub(crate) fn manage_status(current_status: ServerStatus, event: &ServerEvent) -> ServerStatus {
+    let new_status = match event {
+        ServerEvent::Started => ServerStatus::Starting,
+        ServerEvent::Attached { .. } => ServerStatus::Running { info: None },
+        ServerEvent::Ready { startup } => {
+            info!("Minecraft server ready after {:.1}s", startup.as_secs_f32());
+            ServerStatus::Running { info: None }
         }
-        ServerStatus::Running {
-            players,
-            max_players,
-        } => {
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Playing,
-                name: if let Some(max_players) = max_players {
-                    format!("🟢 Online | {}/{}", players, max_players)
-                } else {
-                    "🟢 Online".to_string()
-                },
-                url: None,
-            });
-            UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)
-                .expect("Failed creating UpdatePresence payload")
+        ServerEvent::Stopping => ServerStatus::Stopping,
+        ServerEvent::Stopped { .. } => ServerStatus::Offline,
+        // The count gets corrected with the next ping, this just keeps the presence responsive
+        ServerEvent::PlayerJoined { player } | ServerEvent::PlayerLeft { player } => {
+            let ServerStatus::Running { info: Some(info) } = &current_status else {
+                return current_status;
+            };
+            let mut info = info.clone();
+            if matches!(event, ServerEvent::PlayerJoined { .. }) {
+                info!("{player} joined the game");
+                info.players += 1;
+            } else {
+                info!("{player} left the game");
+                info.players = info.players.saturating_sub(1);
+            }
+            ServerStatus::Running { info: Some(info) }
         }
-        ServerStatus::Stopping => {
-            let activity = Activity::from(MinimalActivity {
-                kind: ActivityType::Playing,
-                name: "🟠 Stopping".to_owned(),
-                url: None,
-            });
-            UpdatePresence::new(Vec::from([activity]), false, None, Status::Online)
-                .expect("Failed creating UpdatePresence payload")
+        ServerEvent::Lagging { behind, ticks } => {
+            warn!(
+                "Minecraft server can't keep up, running {}ms or {ticks} ticks behind",
+                behind.as_millis()
+            );
+            return current_status;
         }
+        _ => return current_status,
     };
+    new_status
+}
+
+/// Shows the status of the servers as the bot's presence. With several servers, every server gets a short
+/// entry and the bot shows as online while any of them is.
+pub(crate) async fn set_status(discord_msg_sender: &MessageSender, servers: &[Server]) {
+    let mut statuses = Vec::with_capacity(servers.len());
+    for server in servers {
+        statuses.push((server.name.as_str(), server.status.read().await.clone()));
+    }
+
+    let (name, status) = if let [(_, status)] = statuses.as_slice() {
+        let name = match status {
+            ServerStatus::Offline => "🔴 Offline".to_string(),
+            ServerStatus::Starting => "🟠 Starting".to_string(),
+            ServerStatus::Running { info: Some(info) } => {
+                format!("🟢 Online | {}/{}", info.players, info.max_players)
+            }
+            ServerStatus::Running { info: None } => "🟢 Online".to_string(),
+            ServerStatus::Stopping => "🟠 Stopping".to_string(),
+        };
+        (name, status_kind(status))
+    } else {
+        let name = statuses
+            .iter()
+            .map(|(name, status)| match status {
+                ServerStatus::Offline => format!("🔴 {name}"),
+                ServerStatus::Starting | ServerStatus::Stopping => format!("🟠 {name}"),
+                ServerStatus::Running { info: Some(info) } => {
+                    format!("🟢 {name} {}/{}", info.players, info.max_players)
+                }
+                ServerStatus::Running { info: None } => format!("🟢 {name}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let status = if statuses
+            .iter()
+            .any(|(_, status)| status_kind(status) == Status::Online)
+        {
+            Status::Online
+        } else {
+            Status::Idle
+        };
+        // Discord cuts activity names off after 128 characters
+        let name = if name.chars().count() > 128 {
+            name.chars().take(127).collect::<String>() + "…"
+        } else {
+            name
+        };
+        (name, status)
+    };
+
+    let activity = Activity::from(MinimalActivity {
+        kind: ActivityType::Playing,
+        name,
+        url: None,
+    });
+    let request = UpdatePresence::new(Vec::from([activity]), false, None, status)
+        .expect("Failed creating UpdatePresence payload");
 
     if let Err(e) = discord_msg_sender.command(&request) {
         warn!("Failed updating discord presence: {e}");
     }
 }
+
+fn status_kind(status: &ServerStatus) -> Status {
+    match status {
+        ServerStatus::Offline => Status::Idle,
+        _ => Status::Online,
+    }
+}
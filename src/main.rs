@@ -1,34 +1,33 @@
+mod audit;
 mod bot;
+mod config;
 mod discord;
 mod minecraft;
+mod permissions;
+mod schedule;
+use config::Config;
 use dotenvy::dotenv;
-use log::info;
-use std::env;
+use log::{error, info};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     dotenv().ok();
     env_logger::init();
 
-    // make sure all environment variables exist at startup
-    env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN env var not found");
-    env::var("CONSOLE_CHANNEL_ID")
-        .expect("CONSOLE_CHANNEL_ID env var not found")
-        .parse::<u64>()
-        .expect("CONSOLE_CHANNEL_ID env var has to be an u64 integer");
-    env::var("SERVER_JAR_PATH").expect("SERVER_JAR_PATH env var not found");
-    env::var("SERVER_MEMORY")
-        .expect("SERVER_MEMORY env var not found")
-        .parse::<u16>()
-        .expect("SERVER_MEMORY env var has to be an u16 integer");
-    let _ = env::var("MAX_PLAYERS").map(|max| {
-        max.parse::<u8>()
-            .expect("MAX_PLAYERS env var has to be an u8 integer")
-    });
+    // make sure the whole config is valid at startup
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(errors) => {
+            for err in &errors {
+                error!("{err}");
+            }
+            anyhow::bail!("Invalid configuration, found {} error(s)", errors.len());
+        }
+    };
 
     info!("Starting up...");
 
-    bot::init().await?;
+    bot::init(config).await?;
 
     Ok(())
 }
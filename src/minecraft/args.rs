@@ -0,0 +1,145 @@
+use super::enums::ArgsError;
+
+/// Splits a command line into arguments like a POSIX shell does, without expanding anything. Single quotes
+/// keep everything literally, double quotes allow escaping `"` and `\` with a backslash and a backslash
+/// outside of quotes escapes any character.
+pub(crate) fn split_args(input: &str) -> Result<Vec<String>, ArgsError> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    // Distinguishes `''` (an empty argument) from no argument at all
+    let mut in_arg = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(ArgsError::UnclosedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err(ArgsError::UnclosedQuote('"')),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err(ArgsError::UnclosedQuote('"')),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                match chars.next() {
+                    Some(c) => arg.push(c),
+                    None => return Err(ArgsError::TrailingBackslash),
+                }
+            }
+            c => {
+                in_arg = true;
+                arg.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+/// Joins arguments into a command line [`split_args`] splits into the same arguments, quoting where needed.
+pub(crate) fn join_args<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_=+:,./@%{}".contains(c));
+            if plain {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join_args, split_args, ArgsError};
+
+    fn split(input: &str) -> Vec<String> {
+        split_args(input).unwrap_or_else(|err| panic!("Failed splitting `{input}`: {err}"))
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(
+            split("  -Xss4M\t-XX:+UseG1GC  nogui "),
+            ["-Xss4M", "-XX:+UseG1GC", "nogui"]
+        );
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            split(r#"tar -czf '/srv/my backups/{BACKUP_NAME}' "/srv/my server""#),
+            [
+                "tar",
+                "-czf",
+                "/srv/my backups/{BACKUP_NAME}",
+                "/srv/my server"
+            ]
+        );
+        assert_eq!(
+            split(r#"-Dmotd="say \"hi\"" 'it''s'"#),
+            [r#"-Dmotd=say "hi""#, "its"]
+        );
+        assert_eq!(split(r#""C:\path" '' """#), [r"C:\path", "", ""]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(split(r"/srv/my\ server \'"), ["/srv/my server", "'"]);
+    }
+
+    #[test]
+    fn joins_what_it_splits() {
+        let args = ["java", "-Dmotd=Hello world", "", "it's", "-XX:+UseG1GC"];
+        let joined = join_args(&args);
+        assert_eq!(
+            joined,
+            r"java '-Dmotd=Hello world' '' 'it'\''s' -XX:+UseG1GC"
+        );
+        assert_eq!(split(&joined), args);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(split_args("'open"), Err(ArgsError::UnclosedQuote('\'')));
+        assert_eq!(split_args(r#""open\""#), Err(ArgsError::UnclosedQuote('"')));
+        assert_eq!(split_args(r"trailing\"), Err(ArgsError::TrailingBackslash));
+    }
+}
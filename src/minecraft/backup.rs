@@ -0,0 +1,650 @@
+use super::enums::{BackupError, RestoreError};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use log::warn;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+use tar::{Builder, EntryType, Header};
+
+/// Compression of a backup, picked by the extension of its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Returns `None` if the name doesn't end in `.tar`, `.tar.gz`, `.tgz`, `.tar.zst` or `.tzst`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Compression::Gzip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Compression::Zstd)
+        } else if name.ends_with(".tar") {
+            Some(Compression::None)
+        } else {
+            None
+        }
+    }
+}
+
+/// What was written into an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Archive {
+    pub(super) files: u64,
+    /// Uncompressed size of the files.
+    pub(super) bytes: u64,
+    /// Size of the archive itself.
+    pub(super) size: u64,
+}
+
+/// Writes the folder into a tar archive at `path`, with every entry below the name of the folder, like
+/// `tar -czf <path> -C <parent> <folder>` does. `exclude` is left out, it's where the backups are stored.
+/// Symlinks are stored as links. `progress` is called with the bytes written so far and the total.
+pub(super) fn create(
+    folder: &Path,
+    path: &Path,
+    compression: Compression,
+    exclude: &Path,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<Archive, BackupError> {
+    let folder = folder
+        .canonicalize()
+        .map_err(|err| BackupError::Read(folder.to_path_buf(), err))?;
+    let root = PathBuf::from(folder.file_name().unwrap_or("server".as_ref()));
+    // Without the folder existing yet, there's nothing to exclude
+    let exclude = exclude.canonicalize().ok();
+
+    let mut entries = Vec::new();
+    collect_entries(&folder, exclude.as_deref(), &mut entries)?;
+    let total_bytes = entries.iter().map(|(_, metadata)| metadata.len()).sum();
+
+    let file = File::create(path).map_err(|err| BackupError::Write(path.to_path_buf(), err))?;
+    let mut builder = Builder::new(
+        Encoder::new(file, compression)
+            .map_err(|err| BackupError::Write(path.to_path_buf(), err))?,
+    );
+    builder.mode(tar::HeaderMode::Complete);
+    builder.follow_symlinks(false);
+
+    let mut archive = Archive {
+        files: 0,
+        bytes: 0,
+        size: 0,
+    };
+    builder
+        .append_dir(&root, &folder)
+        .map_err(|err| BackupError::Add(folder.clone(), err))?;
+    for (source, metadata) in entries {
+        let name = root.join(
+            source
+                .strip_prefix(&folder)
+                .expect("Entries are inside the folder"),
+        );
+        let result = if metadata.is_file() {
+            let file = File::open(&source).map_err(|err| BackupError::Read(source.clone(), err))?;
+            let mut header = Header::new_gnu();
+            header.set_metadata_in_mode(&metadata, tar::HeaderMode::Complete);
+            // Files which change while they're written would corrupt the archive if the size in the header
+            // doesn't match, so the content is cut off or padded to the size it had.
+            let len = metadata.len();
+            let data = Counter {
+                inner: file.take(len).chain(io::repeat(0)).take(len),
+                count: &mut archive.bytes,
+                total: total_bytes,
+                progress: &mut progress,
+            };
+            archive.files += 1;
+            builder.append_data(&mut header, &name, data)
+        } else {
+            builder.append_path_with_name(&source, &name)
+        };
+        result.map_err(|err| BackupError::Add(source, err))?;
+    }
+
+    let file = builder
+        .into_inner()
+        .and_then(Encoder::finish)
+        .map_err(|err| BackupError::Write(path.to_path_buf(), err))?;
+    archive.size = file
+        .metadata()
+        .map_err(|err| BackupError::Read(path.to_path_buf(), err))?
+        .len();
+    Ok(archive)
+}
+
+/// Collects everything inside the folder, parents before their content.
+fn collect_entries(
+    folder: &Path,
+    exclude: Option<&Path>,
+    entries: &mut Vec<(PathBuf, fs::Metadata)>,
+) -> Result<(), BackupError> {
+    let read_dir =
+        fs::read_dir(folder).map_err(|err| BackupError::Read(folder.to_path_buf(), err))?;
+    let mut children = Vec::new();
+    for entry in read_dir {
+        let entry = entry.map_err(|err| BackupError::Read(folder.to_path_buf(), err))?;
+        children.push(entry.path());
+    }
+    children.sort();
+
+    for child in children {
+        if exclude.is_some_and(|exclude| child == exclude) {
+            continue;
+        }
+        let metadata =
+            fs::symlink_metadata(&child).map_err(|err| BackupError::Read(child.clone(), err))?;
+        let is_dir = metadata.is_dir();
+        entries.push((child.clone(), metadata));
+        if is_dir {
+            collect_entries(&child, exclude, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the whole archive back and checks it contains what was written. The checksums of gzip and zstd
+/// catch corrupted data, the tar headers have their own checksum.
+pub(super) fn verify(
+    path: &Path,
+    compression: Compression,
+    expected: &Archive,
+) -> Result<(), BackupError> {
+    let reader =
+        open(path, compression).map_err(|err| BackupError::Read(path.to_path_buf(), err))?;
+
+    let verify_err = |err: io::Error| BackupError::Verify(path.to_path_buf(), err.to_string());
+    let mut archive = tar::Archive::new(reader);
+    let (mut files, mut bytes) = (0, 0);
+    for entry in archive.entries().map_err(verify_err)? {
+        let mut entry = entry.map_err(verify_err)?;
+        if entry.header().entry_type() == EntryType::Regular {
+            files += 1;
+            bytes += io::copy(&mut entry, &mut io::sink()).map_err(verify_err)?;
+        }
+    }
+    // The checksum of the compressed stream is only checked once it's read to the end
+    io::copy(&mut archive.into_inner(), &mut io::sink()).map_err(verify_err)?;
+
+    if (files, bytes) != (expected.files, expected.bytes) {
+        return Err(BackupError::Verify(
+            path.to_path_buf(),
+            format!(
+                "it contains {files} files with {bytes} bytes instead of {} files with {} bytes",
+                expected.files, expected.bytes
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Opens the archive for reading, decompressing it on the fly.
+fn open(path: &Path, compression: Compression) -> io::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(GzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+    })
+}
+
+/// Moves the worlds of the server, the folders containing a `level.dat`, into the `snapshot` folder. If
+/// moving one fails, the ones moved already are moved back.
+pub(super) fn move_worlds(folder: &Path, snapshot: &Path) -> Result<Vec<PathBuf>, RestoreError> {
+    let read_err = |err| RestoreError::Read(folder.to_path_buf(), err);
+    let mut worlds = Vec::new();
+    for entry in fs::read_dir(folder).map_err(read_err)? {
+        let path = entry.map_err(read_err)?.path();
+        if path.join("level.dat").is_file() {
+            worlds.push(path);
+        }
+    }
+    worlds.sort();
+
+    fs::create_dir_all(snapshot)
+        .map_err(|err| RestoreError::Snapshot(snapshot.to_path_buf(), err))?;
+    for (i, world) in worlds.iter().enumerate() {
+        let target = snapshot.join(world.file_name().expect("Worlds are inside the folder"));
+        if let Err(err) = move_dir(world, &target) {
+            for world in &worlds[..i] {
+                let moved = snapshot.join(world.file_name().expect("Worlds are inside the folder"));
+                if let Err(err) = move_dir(&moved, world) {
+                    warn!("Failed moving {} back: {err}", moved.display());
+                }
+            }
+            return Err(RestoreError::Snapshot(world.clone(), err));
+        }
+    }
+    Ok(worlds)
+}
+
+/// Renames the folder, or copies it if the target is on another file system.
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            copy_dir(from, to)?;
+            fs::remove_dir_all(from)
+        }
+        result => result,
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Finds the path in the archive the server folder was stored at, the parent of the shallowest world
+/// folder with a `level.dat`. Archives of [`create`] store it at the name of the folder, ones made with
+/// `tar` can have longer paths like `./server` or `srv/minecraft`.
+pub(super) fn find_root(path: &Path, compression: Compression) -> Result<PathBuf, RestoreError> {
+    let read_err = |err| RestoreError::Read(path.to_path_buf(), err);
+    let mut archive = tar::Archive::new(open(path, compression).map_err(read_err)?);
+
+    let mut root: Option<PathBuf> = None;
+    for entry in archive.entries().map_err(read_err)? {
+        let entry = entry.map_err(read_err)?;
+        let name = normalize(&entry.path().map_err(read_err)?);
+        if name.file_name() != Some("level.dat".as_ref())
+            || !name
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            continue;
+        }
+        // The level.dat has to be inside a world folder
+        let Some(world_root) = name.parent().and_then(Path::parent) else {
+            continue;
+        };
+        if root
+            .as_ref()
+            .is_none_or(|root| world_root.components().count() < root.components().count())
+        {
+            root = Some(world_root.to_path_buf());
+        }
+    }
+    root.ok_or_else(|| {
+        RestoreError::NoWorld(
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        )
+    })
+}
+
+/// Leaves out `.` and a leading `/`, like `tar` does when extracting.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir | Component::RootDir))
+        .collect()
+}
+
+/// Extracts the entries below `root` in the archive into the folder, replacing files which already exist.
+/// `root` is stripped from the entries, everything outside of it is left out.
+pub(super) fn extract(
+    path: &Path,
+    compression: Compression,
+    root: &Path,
+    folder: &Path,
+) -> Result<(), RestoreError> {
+    let read_err = |err| RestoreError::Read(path.to_path_buf(), err);
+    let mut archive = tar::Archive::new(open(path, compression).map_err(read_err)?);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_overwrite(true);
+
+    for entry in archive.entries().map_err(read_err)? {
+        let mut entry = entry.map_err(read_err)?;
+        let name = entry.path().map_err(read_err)?.into_owned();
+        let Ok(relative) = normalize(&name).strip_prefix(root).map(Path::to_path_buf) else {
+            continue;
+        };
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(RestoreError::UnsafePath(name));
+        }
+        // A symlink extracted before mustn't redirect the entry out of the folder
+        let target = folder.join(&relative);
+        let mut parent = folder.to_path_buf();
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            parent.push(component);
+            if fs::symlink_metadata(&parent).is_ok_and(|metadata| metadata.is_symlink()) {
+                return Err(RestoreError::UnsafePath(name));
+            }
+        }
+        if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_symlink()) {
+            fs::remove_file(&target).map_err(|err| RestoreError::Extract(target.clone(), err))?;
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| RestoreError::Extract(target.clone(), err))?;
+        }
+        entry
+            .unpack(&target)
+            .map_err(|err| RestoreError::Extract(target, err))?;
+    }
+    Ok(())
+}
+
+/// Compresses the archive while it's written.
+enum Encoder {
+    None(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(file: File, compression: Compression) -> io::Result<Self> {
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Encoder::None(file),
+            Compression::Gzip => {
+                Encoder::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+                encoder.include_checksum(true)?;
+                Encoder::Zstd(encoder)
+            }
+        })
+    }
+
+    /// Finishes the compressed stream and makes sure everything is on disk.
+    fn finish(self) -> io::Result<File> {
+        let writer = match self {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        let file = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+        Ok(file)
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Counts the bytes read from a file for the progress.
+struct Counter<'a, R, F> {
+    inner: R,
+    count: &'a mut u64,
+    total: u64,
+    progress: &'a mut F,
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Read for Counter<'_, R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        *self.count += read as u64;
+        (self.progress)(*self.count, self.total);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    const COMPRESSIONS: [Compression; 3] =
+        [Compression::None, Compression::Gzip, Compression::Zstd];
+
+    /// An empty folder for the test, removed again by the test once it's done.
+    fn temp_dir(test: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("eve-backup-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    /// A server folder with a world, a config file, a symlink and the backup folder inside it.
+    fn server_folder(base: &Path) -> PathBuf {
+        let server = base.join("server");
+        fs::create_dir_all(server.join("world/region")).unwrap();
+        fs::create_dir_all(server.join("backups")).unwrap();
+        fs::write(server.join("world/level.dat"), "level").unwrap();
+        fs::write(server.join("world/region/r.0.0.mca"), "region").unwrap();
+        fs::write(server.join("server.properties"), "motd=Hello").unwrap();
+        fs::write(server.join("backups/old.tar.gz"), "old backup").unwrap();
+        symlink("server.properties", server.join("link")).unwrap();
+        server
+    }
+
+    /// Writes an archive with the entries as they are, without the checks of [`Header::set_path`].
+    fn raw_archive(path: &Path, entries: &[(&str, EntryType, &str)]) {
+        let mut builder = Builder::new(File::create(path).unwrap());
+        for (name, entry_type, content) in entries {
+            let mut header = Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o755);
+            if *entry_type == EntryType::Symlink {
+                header.as_old_mut().linkname[..content.len()].copy_from_slice(content.as_bytes());
+                header.set_size(0);
+            } else {
+                header.set_size(content.len() as u64);
+            }
+            header.set_cksum();
+            let data = if *entry_type == EntryType::Symlink {
+                &[][..]
+            } else {
+                content.as_bytes()
+            };
+            builder.append(&header, data).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        for compression in COMPRESSIONS {
+            let base = temp_dir(&format!("round-trip-{compression:?}"));
+            let server = server_folder(&base);
+            let path = base.join("backup.tar");
+
+            let archive = create(
+                &server,
+                &path,
+                compression,
+                &server.join("backups"),
+                |_, _| {},
+            )
+            .unwrap();
+            assert_eq!((archive.files, archive.bytes), (3, 21));
+            verify(&path, compression, &archive).unwrap();
+
+            let restored = base.join("restored");
+            fs::create_dir(&restored).unwrap();
+            let root = find_root(&path, compression).unwrap();
+            assert_eq!(root, Path::new("server"));
+            extract(&path, compression, &root, &restored).unwrap();
+            let read = |name: &str| fs::read_to_string(restored.join(name)).unwrap();
+            assert_eq!(read("world/level.dat"), "level");
+            assert_eq!(read("world/region/r.0.0.mca"), "region");
+            assert_eq!(read("server.properties"), "motd=Hello");
+            assert_eq!(
+                fs::read_link(restored.join("link")).unwrap(),
+                Path::new("server.properties")
+            );
+            // The backup folder is excluded
+            assert!(!restored.join("backups").exists());
+
+            fs::remove_dir_all(&base).unwrap();
+        }
+    }
+
+    #[test]
+    fn verify_rejects_truncated_archives() {
+        for compression in COMPRESSIONS {
+            let base = temp_dir(&format!("truncated-{compression:?}"));
+            let server = server_folder(&base);
+            // Enough data that cutting the archive in half cuts into a file
+            fs::write(server.join("world/region/r.0.1.mca"), "x".repeat(100_000)).unwrap();
+            let path = base.join("backup.tar");
+
+            let archive = create(
+                &server,
+                &path,
+                compression,
+                &server.join("backups"),
+                |_, _| {},
+            )
+            .unwrap();
+            let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(archive.size / 2).unwrap();
+            assert!(matches!(
+                verify(&path, compression, &archive),
+                Err(BackupError::Verify(..))
+            ));
+
+            fs::remove_dir_all(&base).unwrap();
+        }
+    }
+
+    #[test]
+    fn extract_rejects_parent_dirs() {
+        let base = temp_dir("parent-dirs");
+        let path = base.join("backup.tar");
+        raw_archive(
+            &path,
+            &[
+                ("server/", EntryType::Directory, ""),
+                ("server/../evil", EntryType::Regular, "evil"),
+            ],
+        );
+        let restored = base.join("restored");
+        fs::create_dir(&restored).unwrap();
+
+        assert!(matches!(
+            extract(&path, Compression::None, Path::new("server"), &restored),
+            Err(RestoreError::UnsafePath(_))
+        ));
+        assert!(!base.join("evil").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn extract_rejects_entries_through_symlinks() {
+        let base = temp_dir("symlinks");
+        let path = base.join("backup.tar");
+        raw_archive(
+            &path,
+            &[
+                ("server/", EntryType::Directory, ""),
+                ("server/link", EntryType::Symlink, ".."),
+                ("server/link/evil", EntryType::Regular, "evil"),
+            ],
+        );
+        let restored = base.join("restored");
+        fs::create_dir(&restored).unwrap();
+
+        assert!(matches!(
+            extract(&path, Compression::None, Path::new("server"), &restored),
+            Err(RestoreError::UnsafePath(_))
+        ));
+        assert!(!base.join("evil").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn finds_root_of_tar_archives() {
+        let base = temp_dir("tar-roots");
+        let path = base.join("backup.tar");
+        for prefix in ["./server/", "/srv/minecraft/", "./"] {
+            let name = |name: &str| format!("{prefix}{name}");
+            raw_archive(
+                &path,
+                &[
+                    (&name(""), EntryType::Directory, ""),
+                    (&name("world/"), EntryType::Directory, ""),
+                    (&name("world/level.dat"), EntryType::Regular, "level"),
+                    (
+                        &name("world/region/r.0.0.mca"),
+                        EntryType::Regular,
+                        "region",
+                    ),
+                    (&name("server.properties"), EntryType::Regular, "motd=Hello"),
+                    // Worlds deeper down don't change the root
+                    (&name("old/world/level.dat"), EntryType::Regular, "old"),
+                ],
+            );
+            let restored = base.join("restored");
+            fs::create_dir(&restored).unwrap();
+
+            let root = find_root(&path, Compression::None).unwrap();
+            let expected = prefix.trim_start_matches(['.', '/']).trim_end_matches('/');
+            assert_eq!(root, Path::new(expected));
+            extract(&path, Compression::None, &root, &restored).unwrap();
+            let read = |name: &str| fs::read_to_string(restored.join(name)).unwrap();
+            assert_eq!(read("world/level.dat"), "level");
+            assert_eq!(read("world/region/r.0.0.mca"), "region");
+            assert_eq!(read("server.properties"), "motd=Hello");
+
+            fs::remove_dir_all(&restored).unwrap();
+        }
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn find_root_rejects_archives_without_worlds() {
+        let base = temp_dir("no-worlds");
+        let path = base.join("backup.tar");
+        raw_archive(
+            &path,
+            &[
+                ("server/", EntryType::Directory, ""),
+                ("server/server.properties", EntryType::Regular, "motd=Hello"),
+                // Not inside a world folder
+                ("level.dat", EntryType::Regular, "level"),
+            ],
+        );
+
+        assert!(matches!(
+            find_root(&path, Compression::None),
+            Err(RestoreError::NoWorld(name)) if name == "backup.tar"
+        ));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,34 +1,400 @@
 use super::enums::ServerConfigError;
-use std::path::PathBuf;
+use super::retention::Retention;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    time::Duration,
+};
+
+/// Flags of Aikar's preset, see <https://docs.papermc.io/paper/aikars-flags>. The G1 sizes depend on the heap
+/// and are added separately.
+const AIKAR_FLAGS: [&str; 15] = [
+    "-XX:+UseG1GC",
+    "-XX:+ParallelRefProcEnabled",
+    "-XX:MaxGCPauseMillis=200",
+    "-XX:+UnlockExperimentalVMOptions",
+    "-XX:+DisableExplicitGC",
+    "-XX:+AlwaysPreTouch",
+    "-XX:G1HeapWastePercent=5",
+    "-XX:G1MixedGCCountTarget=4",
+    "-XX:G1MixedGCLiveThresholdPercent=90",
+    "-XX:G1RSetUpdatingPauseTimePercent=5",
+    "-XX:SurvivorRatio=32",
+    "-XX:+PerfDisableSharedMem",
+    "-XX:MaxTenuringThreshold=1",
+    "-Dusing.aikars.flags=https://mcflags.emc.gs",
+    "-Daikars.new.flags=true",
+];
+/// G1 sizes of Aikar's preset for heaps up to 12 GB.
+const AIKAR_SIZES: [&str; 5] = [
+    "-XX:G1NewSizePercent=30",
+    "-XX:G1MaxNewSizePercent=40",
+    "-XX:G1HeapRegionSize=8M",
+    "-XX:G1ReservePercent=20",
+    "-XX:InitiatingHeapOccupancyPercent=15",
+];
+/// G1 sizes of Aikar's preset for heaps above 12 GB.
+const AIKAR_LARGE_SIZES: [&str; 5] = [
+    "-XX:G1NewSizePercent=40",
+    "-XX:G1MaxNewSizePercent=50",
+    "-XX:G1HeapRegionSize=16M",
+    "-XX:G1ReservePercent=15",
+    "-XX:InitiatingHeapOccupancyPercent=20",
+];
+const ZGC_FLAGS: [&str; 4] = [
+    "-XX:+UseZGC",
+    "-XX:+AlwaysPreTouch",
+    "-XX:+DisableExplicitGC",
+    "-XX:+PerfDisableSharedMem",
+];
+
+/// Named sets of JVM flags tuned for Minecraft servers, passed before the `jvm_args`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum JvmPreset {
+    /// Only the memory flags.
+    #[default]
+    Default,
+    /// Aikar's flags, with the sizes for large heaps if the server has more than 12 GB.
+    Aikar,
+    /// Aikar's flags with the sizes for large heaps, regardless of the memory.
+    AikarLarge,
+    /// The Z garbage collector, for large heaps on Java 17 and newer.
+    Zgc,
+}
+
+impl JvmPreset {
+    fn flags(self, memory: Option<u16>) -> Vec<String> {
+        let large = memory.is_some_and(|memory| memory > 12 * 1024);
+        let flags: Vec<&str> = match self {
+            JvmPreset::Default => Vec::new(),
+            JvmPreset::Aikar if !large => [&AIKAR_FLAGS[..], &AIKAR_SIZES].concat(),
+            JvmPreset::Aikar | JvmPreset::AikarLarge => {
+                [&AIKAR_FLAGS[..], &AIKAR_LARGE_SIZES].concat()
+            }
+            JvmPreset::Zgc => ZGC_FLAGS.to_vec(),
+        };
+        flags.into_iter().map(str::to_string).collect()
+    }
+}
+
+impl FromStr for JvmPreset {
+    type Err = ();
+
+    fn from_str(preset: &str) -> Result<Self, Self::Err> {
+        match preset.trim().to_lowercase().as_str() {
+            "default" => Ok(JvmPreset::Default),
+            "aikar" => Ok(JvmPreset::Aikar),
+            "aikar-large" => Ok(JvmPreset::AikarLarge),
+            "zgc" => Ok(JvmPreset::Zgc),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for JvmPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JvmPreset::Default => "default",
+            JvmPreset::Aikar => "aikar",
+            JvmPreset::AikarLarge => "aikar-large",
+            JvmPreset::Zgc => "zgc",
+        })
+    }
+}
+
+/// How the server process is started.
+#[derive(Debug, Clone)]
+pub(crate) enum Launch {
+    /// Runs the server jar with `java -Xms -Xmx <jvm args> -jar <jar> <server args>` in the jar's folder.
+    Jar { path: PathBuf, java_path: PathBuf },
+    /// Runs a custom command or script, e.g. the `run.sh` of Forge or a Bedrock server.
+    Command(Vec<String>),
+}
 
 #[derive(Debug, Clone)]
 pub(crate) struct ServerConfig {
-    pub(super) path: PathBuf,
-    pub(super) memory: u16,
-    pub(super) jvm_flags: Option<String>,
+    pub(super) launch: Launch,
+    /// Folder the server runs in.
+    pub(super) folder: PathBuf,
+    /// Memory in megabytes, only optional for custom commands.
+    pub(super) memory: Option<u16>,
+    pub(super) jvm_preset: JvmPreset,
+    /// Arguments for the JVM, passed before `-jar`.
+    pub(super) jvm_args: Vec<String>,
+    /// Arguments for the server, passed after the jar.
+    pub(super) server_args: Vec<String>,
     pub(super) auto_accept_eula: bool,
 }
 
 impl ServerConfig {
-    pub fn new<P: Into<PathBuf>>(
-        server_path: P,
-        memory: u16,
-        jvm_flags: Option<String>,
+    pub fn new(
+        launch: Launch,
+        folder: PathBuf,
+        memory: Option<u16>,
+        jvm_preset: JvmPreset,
+        jvm_args: Vec<String>,
+        server_args: Vec<String>,
         auto_accept_eula: bool,
     ) -> Self {
-        let path = server_path.into();
         ServerConfig {
-            path,
+            launch,
+            folder,
             memory,
-            jvm_flags,
+            jvm_preset,
+            jvm_args,
+            server_args,
             auto_accept_eula,
         }
     }
 
+    /// Folder the server runs in.
+    pub(super) fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    pub(super) fn log_path(&self) -> PathBuf {
+        self.folder().join("logs").join("latest.log")
+    }
+
+    /// The program launched, which the PID file's process is compared with.
+    pub(super) fn program(&self) -> &Path {
+        match &self.launch {
+            Launch::Jar { java_path, .. } => java_path,
+            Launch::Command(command) => Path::new(&command[0]),
+        }
+    }
+
     pub fn validate(&self) -> Result<(), ServerConfigError> {
-        if !self.path.is_file() {
-            return Err(ServerConfigError::InvalidPath(self.path.clone()));
+        match &self.launch {
+            Launch::Jar { path, .. } if !path.is_file() => {
+                Err(ServerConfigError::InvalidPath(path.clone()))
+            }
+            _ if !self.folder.is_dir() => {
+                Err(ServerConfigError::InvalidFolder(self.folder.clone()))
+            }
+            _ => Ok(()),
         }
-        Ok(())
     }
+
+    /// Builds the command which launches the server, to run in [`Self::folder`].
+    ///
+    /// Custom commands get the memory, the preset's flags and the JVM arguments where they contain a
+    /// `{JVM_ARGS}` argument, or else through `JDK_JAVA_OPTIONS`, which every `java` started by a script
+    /// picks up. The server arguments go where the command contains a `{SERVER_ARGS}` argument, or else to
+    /// its end. `{MEMORY}` is replaced with the memory in megabytes anywhere.
+    pub(super) fn command(&self) -> Command {
+        let mut jvm_args: Vec<String> = self
+            .memory
+            .map(|memory| vec![format!("-Xms{memory}M"), format!("-Xmx{memory}M")])
+            .unwrap_or_default();
+        jvm_args.extend(self.jvm_preset.flags(self.memory));
+        jvm_args.extend(self.jvm_args.iter().cloned());
+
+        match &self.launch {
+            Launch::Jar { path, java_path } => {
+                let mut command = Command::new(java_path);
+                command
+                    .args(jvm_args)
+                    .arg("-jar")
+                    // The server runs in the working dir, which doesn't have to contain the jar
+                    .arg(std::path::absolute(path).unwrap_or_else(|_| path.clone()))
+                    .args(&self.server_args);
+                command
+            }
+            Launch::Command(custom) => {
+                let memory = self
+                    .memory
+                    .map(|memory| memory.to_string())
+                    .unwrap_or_default();
+                let mut args = Vec::new();
+                for arg in custom {
+                    match arg.as_str() {
+                        "{JVM_ARGS}" => args.extend(jvm_args.iter().cloned()),
+                        "{SERVER_ARGS}" => args.extend(self.server_args.iter().cloned()),
+                        arg => args.push(arg.replace("{MEMORY}", &memory)),
+                    }
+                }
+                if !custom.iter().any(|arg| arg == "{SERVER_ARGS}") {
+                    args.extend(self.server_args.iter().cloned());
+                }
+
+                let (program, args) = args
+                    .split_first()
+                    .expect("Commands start with the program, the config checks it");
+                let mut command = Command::new(program);
+                command.args(args);
+                if !custom.iter().any(|arg| arg == "{JVM_ARGS}") && !jvm_args.is_empty() {
+                    command.env("JDK_JAVA_OPTIONS", java_options(&jvm_args));
+                }
+                command
+            }
+        }
+    }
+}
+
+/// Joins the arguments for `JDK_JAVA_OPTIONS`, which splits on whitespace and understands double quotes.
+fn java_options(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("\"{arg}\"")
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RestartPolicy {
+    pub(super) max_crashes: u32,
+    pub(super) window: Duration,
+    pub(super) backoff: Duration,
+}
+
+impl RestartPolicy {
+    pub fn new(max_crashes: u32, window: Duration, backoff: Duration) -> Self {
+        RestartPolicy {
+            max_crashes,
+            window,
+            backoff,
+        }
+    }
+
+    /// Delay before the next restart attempt, doubling with every crash inside the window.
+    pub(super) fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+
+    pub(crate) fn max_crashes(&self) -> u32 {
+        self.max_crashes
+    }
+
+    pub(crate) fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub(crate) fn backoff(&self) -> Duration {
+        self.backoff
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RconConfig {
+    pub(super) address: String,
+    pub(super) password: String,
+}
+
+impl RconConfig {
+    pub fn new(address: String, password: String) -> Self {
+        RconConfig { address, password }
+    }
+
+    pub(crate) fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub(crate) fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BackupConfig {
+    pub(super) folder: PathBuf,
+    pub(super) server_folder: PathBuf,
+    /// Format string for [`astrolabe::DateTime::format`].
+    pub(super) name: String,
+    /// Command and its arguments, which can contain the `{BACKUP_FOLDER}`, `{BACKUP_NAME}` and
+    /// `{SERVER_FOLDER}` placeholders.
+    pub(super) command: Option<Vec<String>>,
+    /// Old backups are pruned after every backup, `None` keeps all of them.
+    pub(super) retention: Option<Retention>,
+}
+
+impl BackupConfig {
+    pub fn new(
+        folder: PathBuf,
+        server_folder: PathBuf,
+        name: String,
+        command: Option<Vec<String>>,
+        retention: Option<Retention>,
+    ) -> Self {
+        BackupConfig {
+            folder,
+            server_folder,
+            name,
+            command,
+            retention,
+        }
+    }
+
+    pub(crate) fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    pub(crate) fn server_folder(&self) -> &Path {
+        &self.server_folder
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn command(&self) -> Option<&[String]> {
+        self.command.as_deref()
+    }
+
+    pub(crate) fn retention(&self) -> Option<&Retention> {
+        self.retention.as_ref()
+    }
+}
+
+/// Wake on join, which listens on the game port while the server is offline and starts it once a player tries
+/// to join.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WakeConfig {
+    /// Address to listen on, the one the server binds when it's running.
+    pub(super) address: String,
+    /// MOTD shown in the server list.
+    pub(super) motd: String,
+    /// Message players trying to join are kicked with.
+    pub(super) kick_message: String,
+}
+
+impl WakeConfig {
+    pub fn new(address: String, motd: String, kick_message: String) -> Self {
+        WakeConfig {
+            address,
+            motd,
+            kick_message,
+        }
+    }
+
+    pub(crate) fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub(crate) fn motd(&self) -> &str {
+        &self.motd
+    }
+
+    pub(crate) fn kick_message(&self) -> &str {
+        &self.kick_message
+    }
+}
+
+/// Settings of the [`ServerManager`](super::ServerManager) which can be changed while it's running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ManagerSettings {
+    pub(crate) restart_policy: RestartPolicy,
+    /// Grace period for `/stop` and `/restart`.
+    pub(crate) stop_timeout: Duration,
+    pub(crate) rcon: Option<RconConfig>,
+    pub(crate) backup: Option<BackupConfig>,
+    pub(crate) wake: Option<WakeConfig>,
 }
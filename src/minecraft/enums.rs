@@ -1,35 +1,266 @@
-use super::config::ServerConfig;
-use std::{io, path::PathBuf};
+use super::{
+    config::{JvmPreset, ServerConfig},
+    ping::PingResponse,
+};
+use std::{io, path::PathBuf, process::ExitStatus, time::Duration};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub(crate) enum ServerConfigError {
     #[error("Server path {0} does not point to a file.")]
     InvalidPath(PathBuf),
+    #[error("Server folder {0} is not a directory.")]
+    InvalidFolder(PathBuf),
 }
 
 #[derive(Debug, Clone)]
 pub(crate) enum ServerCommand {
     Stdin(String),
-    StartServer { config: ServerConfig },
+    StartServer {
+        config: ServerConfig,
+    },
+    Stop,
+    Restart {
+        countdown: Vec<Duration>,
+    },
     Backup,
+    /// Replaces the worlds with the backup of that name, stopping the server while it's restored.
+    Restore {
+        backup: String,
+    },
+}
+
+/// Everything that happens with the server, broadcast by the `ServerManager`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ServerEvent {
+    /// A line the server printed to stdout/stderr, or to its log file if EVE is attached.
+    ConsoleLine(String),
+    /// The server is launched with this command line.
+    Launching {
+        preset: JvmPreset,
+        command_line: String,
+    },
+    Started,
+    /// A player tried to join the offline server while wake on join is enabled.
+    WakeRequested {
+        player: String,
+    },
+    StartFailed {
+        error: String,
+    },
+    EulaAccepted,
+    /// EVE attached to a server it didn't launch itself.
+    Attached {
+        pid: Option<u32>,
+        commands_available: bool,
+    },
+    /// The server finished loading and players can join.
+    Ready {
+        startup: Duration,
+    },
+    Stopping,
+    /// The server didn't stop within the grace period, so it received `signal`.
+    StopEscalated {
+        signal: &'static str,
+        grace_period: Duration,
+    },
+    /// The server is still running after `SIGKILL`.
+    StopFailed,
+    /// `exit_status` is `None` if the server wasn't launched by EVE.
+    Stopped {
+        exit_status: Option<ExitStatus>,
+    },
+    Crashed {
+        exit_status: ExitStatus,
+        restart: Option<CrashRestart>,
+    },
+    /// The server crashed too often, it won't be restarted automatically anymore.
+    RestartsExhausted {
+        exit_status: ExitStatus,
+        crashes: u32,
+        window: Duration,
+    },
+    PlayerJoined {
+        player: String,
+    },
+    PlayerLeft {
+        player: String,
+    },
+    Chat {
+        player: String,
+        message: String,
+    },
+    /// `message` is the whole death message, including the player name.
+    Death {
+        player: String,
+        message: String,
+    },
+    Advancement {
+        player: String,
+        advancement: String,
+    },
+    /// The server is overloaded and can't keep up with its tick rate.
+    Lagging {
+        behind: Duration,
+        ticks: u32,
+    },
+    /// `bytes` of the server folder's `total_bytes` are written into the backup so far.
+    BackupProgress {
+        bytes: u64,
+        total_bytes: u64,
+    },
+    /// `size` is the size of the archive, `None` if a custom backup command created it.
+    BackupFinished {
+        name: String,
+        size: Option<u64>,
+    },
+    BackupFailed {
+        reason: String,
+    },
+    /// Old backups deleted by the retention policy, or the ones it would delete in a dry run.
+    BackupsPruned {
+        deleted: Vec<String>,
+        freed: u64,
+        dry_run: bool,
+    },
+    PruneFailed {
+        reason: String,
+    },
+    RestoreStarted {
+        name: String,
+    },
+    /// The worlds which were replaced are kept in the `snapshot` folder.
+    RestoreFinished {
+        name: String,
+        snapshot: PathBuf,
+    },
+    RestoreFailed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CrashRestart {
+    pub(crate) delay: Duration,
+    pub(crate) attempt: u32,
+    pub(crate) max_crashes: u32,
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum ServerStartError {
     #[error("config error: {0}")]
     ConfigError(#[from] ServerConfigError),
+    #[error("{0} not found, check `java_path` or `command`")]
+    ProgramNotFound(PathBuf),
     #[error("io error: {0}")]
     IoError(#[from] io::Error),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Error, Debug)]
+pub(crate) enum RconError {
+    #[error("io error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("authentication failed, check the RCON password")]
+    AuthFailed,
+    #[error("command is {0} bytes long, RCON only allows 1446 bytes")]
+    CommandTooLong(usize),
+    #[error("invalid packet: {0}")]
+    InvalidPacket(String),
+    #[error("timed out")]
+    Timeout,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum StdinError {
+    #[error("the server isn't running")]
+    NotRunning,
+    #[error("io error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("attached servers can only receive commands over RCON, which isn't configured")]
+    NoRcon,
+    #[error("rcon error: {0}")]
+    RconError(#[from] RconError),
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum BackupError {
+    #[error("backups aren't configured, set `backup.folder` and `backup.server_folder`")]
+    NotConfigured,
+    #[error("`save-off` did not run successfully")]
+    SaveOff,
+    #[error("`save-all` did not run successfully")]
+    SaveAll,
+    #[error("`{0}` doesn't end in `.tar`, `.tar.gz`, `.tgz`, `.tar.zst` or `.tzst`")]
+    UnknownFormat(String),
+    #[error("failed reading {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("failed adding {0} to the archive: {1}")]
+    Add(PathBuf, io::Error),
+    #[error("failed writing {0}: {1}")]
+    Write(PathBuf, io::Error),
+    #[error("verifying {0} failed: {1}")]
+    Verify(PathBuf, String),
+    #[error("backup command failed: {0}")]
+    CommandFailed(io::Error),
+    #[error("backup command exited with {status}: {stderr}")]
+    CommandExited { status: ExitStatus, stderr: String },
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum RestoreError {
+    #[error("backups aren't configured, set `backup.folder` and `backup.server_folder`")]
+    NotConfigured,
+    #[error("there's no backup called `{0}`")]
+    NotFound(String),
+    #[error("`{0}` isn't an archive EVE can restore, it has to end in `.tar`, `.tar.gz`, `.tgz`, `.tar.zst` or `.tzst`")]
+    UnknownFormat(String),
+    #[error("`{0}` has no world folder with a `level.dat`")]
+    NoWorld(String),
+    #[error("the server didn't stop")]
+    StillRunning,
+    #[error("failed reading {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("failed moving {0} into the snapshot: {1}")]
+    Snapshot(PathBuf, io::Error),
+    #[error("{0} in the archive points outside of the server folder")]
+    UnsafePath(PathBuf),
+    #[error("failed extracting {0}: {1}")]
+    Extract(PathBuf, io::Error),
+    /// Extracting failed after the worlds were moved into the snapshot.
+    #[error("{error}, the previous worlds are in {snapshot}")]
+    Incomplete {
+        error: Box<RestoreError>,
+        snapshot: PathBuf,
+    },
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub(crate) enum ArgsError {
+    #[error("missing closing {0} quote")]
+    UnclosedQuote(char),
+    #[error("ends with a backslash which doesn't escape anything")]
+    TrailingBackslash,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum PingError {
+    #[error("io error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("invalid address {0}")]
+    InvalidAddress(String),
+    #[error("invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("invalid status json: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum ServerStatus {
     Offline,
     Starting,
+    /// `info` is `None` until the first successful Server List Ping.
     Running {
-        players: u8,
-        max_players: Option<u8>,
+        info: Option<PingResponse>,
     },
     Stopping,
 }
@@ -1,48 +1,42 @@
-use super::{config::ServerConfig, enums::ServerStartError};
-use log::info;
+use super::{
+    args::join_args,
+    config::ServerConfig,
+    enums::{ServerEvent, ServerStartError},
+    parser::{LogEvent, LogLine},
+    rcon::RconClient,
+};
+use log::{info, warn};
 use std::{
-    ffi::OsStr,
     fs::{self, File},
-    io::{self, Write},
-    path::Path,
+    io::{self, SeekFrom, Write},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
     process::{ExitStatus, Stdio},
 };
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    fs::File as AsyncFile,
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
     process::{self, Child},
     sync::broadcast,
+    time::{self, Duration},
 };
 
+const PID_FILE: &str = "eve.pid";
+
 pub(super) struct ServerInternal {
-    pub(super) stdin: process::ChildStdin,
+    /// `None` if EVE attached to a server it didn't launch itself.
+    pub(super) stdin: Option<process::ChildStdin>,
+    pub(super) pid: Option<u32>,
 }
 
 impl ServerInternal {
     pub(super) async fn launch(
         config: &ServerConfig,
-        stdout_sender: broadcast::Sender<String>,
+        event_sender: broadcast::Sender<ServerEvent>,
     ) -> Result<(Self, Child), ServerStartError> {
         config.validate()?;
 
-        let folder = config
-            .path
-            .as_path()
-            .parent()
-            .map(|p| p.as_os_str())
-            .unwrap_or_else(|| OsStr::new("."));
-
-        let server_jar = config
-            .path
-            .file_name()
-            .expect("Failed getting file name of server jar");
-
-        let args = format!(
-            "-Xms{}M -Xmx{}M -jar {} {} nogui",
-            config.memory,
-            config.memory,
-            server_jar.to_str().unwrap_or(""),
-            config.jvm_flags.as_deref().unwrap_or(""),
-        );
+        let folder = config.folder().as_os_str();
 
         let eula_path = &format!("{}/eula.txt", folder.to_str().unwrap_or("."));
 
@@ -53,33 +47,64 @@ impl ServerInternal {
                     .contains("eula=true"))
         {
             info!("Accepting eula");
-            stdout_sender
-                .send(":green_circle: Accepting eula".to_string())
+            event_sender
+                .send(ServerEvent::EulaAccepted)
                 .expect("Failed sending value over sender");
 
             let mut eula_file = File::create(eula_path)?;
             eula_file.write_all(b"eula=true")?;
         }
 
-        let mut child = process::Command::new("java")
+        let mut command = config.command();
+        let command_line = command_line(&command);
+        info!("Launching server: {command_line}");
+        event_sender
+            .send(ServerEvent::Launching {
+                preset: config.jvm_preset,
+                command_line,
+            })
+            .expect("Failed sending value over sender");
+        command
             .current_dir(folder)
-            .args(args.split(' ').collect::<Vec<&str>>())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()?;
+            // Keep signals meant for EVE (e.g. Ctrl+C) away from the server, EVE stops it gracefully
+            .process_group(0);
+        let mut child =
+            process::Command::from(command)
+                .spawn()
+                .map_err(|err| match err.kind() {
+                    io::ErrorKind::NotFound => {
+                        ServerStartError::ProgramNotFound(config.program().to_path_buf())
+                    }
+                    _ => err.into(),
+                })?;
 
         let stdin = child
             .stdin
             .take()
             .expect("Failed getting stdin of minecraft process");
 
-        Ok((Self { stdin }, child))
+        let pid = child.id();
+        if let Some(pid) = pid {
+            if let Err(err) = fs::write(config.folder().join(PID_FILE), pid.to_string()) {
+                warn!("Failed writing PID file: {err}");
+            }
+        }
+
+        Ok((
+            Self {
+                stdin: Some(stdin),
+                pid,
+            },
+            child,
+        ))
     }
 
     pub(super) async fn run(
         mut process: Child,
-        stdout_sender: broadcast::Sender<String>,
+        event_sender: broadcast::Sender<ServerEvent>,
     ) -> io::Result<ExitStatus> {
         let mut stdout = BufReader::new(
             process
@@ -98,16 +123,14 @@ impl ServerInternal {
 
         let await_process = tokio::spawn(async move { process.wait().await });
 
-        let stdout_sender_clone = stdout_sender.clone();
+        let event_sender_clone = event_sender.clone();
         let stderr_handle = tokio::spawn(async move {
             while let Some(line) = stderr
                 .next_line()
                 .await
                 .expect("Failed reading line from stderr of minecraft process")
             {
-                stdout_sender_clone
-                    .send(line)
-                    .expect("Failed sending value over sender");
+                send_line(&event_sender_clone, line);
             }
         });
         let stdout_handle = tokio::spawn(async move {
@@ -116,9 +139,7 @@ impl ServerInternal {
                 .await
                 .expect("Failed reading line from stdout of minecraft process")
             {
-                stdout_sender
-                    .send(line)
-                    .expect("Failed sending value over sender");
+                send_line(&event_sender, line);
             }
         });
 
@@ -126,4 +147,161 @@ impl ServerInternal {
 
         status.unwrap_or_else(|err| panic!("Failed joining tokio task: {err}"))
     }
+
+    /// Follows `logs/latest.log` of a server EVE attached to, until the server stops. The server is
+    /// considered stopped once its process is gone or, without a PID, once the RCON connection closes.
+    pub(super) async fn follow(
+        config: ServerConfig,
+        pid: Option<u32>,
+        rcon: Option<RconClient>,
+        event_sender: broadcast::Sender<ServerEvent>,
+    ) -> io::Result<Option<ExitStatus>> {
+        let log_path = config.log_path();
+        let program = config.program().to_path_buf();
+        let exited = async move {
+            match (pid, rcon) {
+                (Some(pid), _) => {
+                    while process_alive(pid, &program) {
+                        time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+                (None, Some(mut rcon)) => rcon.closed().await,
+                (None, None) => {}
+            }
+        };
+
+        tokio::select! {
+            _ = exited => Ok(None),
+            result = follow_log(log_path, event_sender) => result.map(|_| None),
+        }
+    }
+
+    pub(super) fn read_pid_file(config: &ServerConfig) -> Option<u32> {
+        fs::read_to_string(config.folder().join(PID_FILE))
+            .ok()
+            .and_then(|pid| pid.trim().parse().ok())
+    }
+
+    pub(super) fn remove_pid_file(config: &ServerConfig) {
+        let _ = fs::remove_file(config.folder().join(PID_FILE));
+    }
+}
+
+/// Renders the command as it could be typed into a shell, including the environment variables it sets.
+fn command_line(command: &std::process::Command) -> String {
+    let mut parts: Vec<String> = command
+        .get_envs()
+        .filter_map(|(key, value)| {
+            let value = value?.to_string_lossy();
+            Some(format!("{}={}", key.to_string_lossy(), join_args(&[value])))
+        })
+        .collect();
+    parts.push(join_args(&[command.get_program().to_string_lossy()]));
+    parts.extend(
+        command
+            .get_args()
+            .map(|arg| join_args(&[arg.to_string_lossy()])),
+    );
+    parts.join(" ")
+}
+
+/// Checks if a process with this PID exists and, where `/proc` is available, that it runs the program, so a
+/// reused PID isn't mistaken for the server.
+pub(super) fn process_alive(pid: u32, program: &Path) -> bool {
+    // SAFETY: `kill` has no memory safety requirements, signal 0 only checks if the process exists
+    let exists = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0
+        || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    if !exists {
+        return false;
+    }
+
+    match fs::read(format!("/proc/{pid}/cmdline")) {
+        Ok(cmdline) => {
+            let program = program.file_name().unwrap_or(program.as_os_str());
+            String::from_utf8_lossy(&cmdline).contains(&*program.to_string_lossy())
+        }
+        Err(_) => true,
+    }
+}
+
+/// Sends the line as console output, followed by the event it stands for if there is one.
+fn send_line(event_sender: &broadcast::Sender<ServerEvent>, line: String) {
+    let event = line_event(&line);
+    event_sender
+        .send(ServerEvent::ConsoleLine(line))
+        .expect("Failed sending value over sender");
+    if let Some(event) = event {
+        event_sender
+            .send(event)
+            .expect("Failed sending value over sender");
+    }
+}
+
+fn line_event(line: &str) -> Option<ServerEvent> {
+    let event = match LogLine::parse(line)?.event()? {
+        LogEvent::Joined { player } => ServerEvent::PlayerJoined { player },
+        LogEvent::Left { player } => ServerEvent::PlayerLeft { player },
+        LogEvent::Chat { player, message } => ServerEvent::Chat { player, message },
+        LogEvent::Death { player, message } => ServerEvent::Death { player, message },
+        LogEvent::Advancement {
+            player,
+            advancement,
+        } => ServerEvent::Advancement {
+            player,
+            advancement,
+        },
+        LogEvent::Done { startup } => ServerEvent::Ready { startup },
+        LogEvent::Lagging { behind, ticks } => ServerEvent::Lagging { behind, ticks },
+        LogEvent::Stopping => ServerEvent::Stopping,
+    };
+    Some(event)
+}
+
+/// Sends every line appended to the log file over the sender, like `tail -F`. Runs until it's dropped once
+/// the server stopped, a missing log file is waited for.
+async fn follow_log(path: PathBuf, event_sender: broadcast::Sender<ServerEvent>) -> io::Result<()> {
+    let mut position = 0;
+    let file = match AsyncFile::open(&path).await {
+        Ok(mut file) => {
+            position = file.seek(SeekFrom::End(0)).await?;
+            file
+        }
+        // The server is starting or just rotated the log, the new file is read from the beginning
+        Err(_) => open_log(&path).await,
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+
+    loop {
+        let read = reader.read_line(&mut line).await?;
+        position += read as u64;
+
+        if read > 0 && line.ends_with('\n') {
+            send_line(&event_sender, line.trim_end().to_string());
+            line.clear();
+            continue;
+        }
+
+        time::sleep(Duration::from_millis(500)).await;
+
+        // The server rotates the log on startup, start reading the new file from the beginning
+        let rotated = fs::metadata(&path).is_ok_and(|metadata| metadata.len() < position);
+        if rotated {
+            reader = BufReader::new(open_log(&path).await);
+            position = 0;
+            line.clear();
+        }
+    }
+}
+
+/// Opens the log file, retrying until it exists.
+async fn open_log(path: &Path) -> AsyncFile {
+    loop {
+        match AsyncFile::open(path).await {
+            Ok(file) => return file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!("Failed opening {}: {err}", path.display()),
+        }
+        time::sleep(Duration::from_millis(500)).await;
+    }
 }
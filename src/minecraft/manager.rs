@@ -1,35 +1,88 @@
-use super::{enums::ServerCommand, internal::ServerInternal};
+use super::{
+    backup::{self, Compression},
+    config::{BackupConfig, ManagerSettings, RconConfig, ServerConfig},
+    enums::{
+        BackupError, CrashRestart, RconError, RestoreError, ServerCommand, ServerEvent, StdinError,
+    },
+    internal::{process_alive, ServerInternal},
+    parser::LogLine,
+    rcon::RconClient,
+    retention::{self, Backup, Retention},
+    wake,
+};
 use astrolabe::DateTime;
-use log::{info, warn};
-use std::{env, process::Stdio, sync::Arc, time::Duration};
+use log::{error, info, warn};
+use std::{
+    collections::VecDeque,
+    fs,
+    future::Future,
+    io,
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
 use tokio::{
     io::AsyncWriteExt,
     process::Command,
-    sync::{broadcast, mpsc, Mutex},
+    sync::{broadcast, mpsc, Mutex, Notify},
+    task::{self, JoinHandle},
+    time,
 };
 
+/// Server events buffered for every receiver, a burst of console output sends two events per line.
+const EVENT_CAPACITY: usize = 1024;
+/// How often the progress of a backup is reported at most.
+const BACKUP_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
 pub(crate) struct ServerManager {
     internal: Arc<Mutex<Option<ServerInternal>>>,
-    stdout_sender: broadcast::Sender<String>,
+    event_sender: broadcast::Sender<ServerEvent>,
+    cmd_sender: mpsc::Sender<ServerCommand>,
+    settings: RwLock<ManagerSettings>,
+    rcon: Mutex<Option<RconClient>>,
+    stop_requested: AtomicBool,
+    /// Set while a restart counts down until the server was launched again, so the wake listener doesn't
+    /// start in between.
+    restarting: AtomicBool,
+    config: Mutex<Option<ServerConfig>>,
+    crashes: Mutex<VecDeque<Instant>>,
+    stopped: Notify,
+    /// Listens on the game port while the server is offline, see [`wake::listen`].
+    wake_listener: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl ServerManager {
-    pub(crate) fn new() -> (
+    pub(crate) fn new(
+        settings: ManagerSettings,
+    ) -> (
         Arc<Self>,
         mpsc::Sender<ServerCommand>,
-        broadcast::Receiver<String>,
+        broadcast::Receiver<ServerEvent>,
     ) {
         let (cmd_sender, cmd_receiver) = mpsc::channel::<ServerCommand>(64);
-        let (stdout_sender, stdout_receiver) = broadcast::channel(64);
+        let (event_sender, event_receiver) = broadcast::channel(EVENT_CAPACITY);
 
         let server = Arc::new(ServerManager {
             internal: Arc::new(Mutex::new(None)),
-            stdout_sender,
+            event_sender,
+            cmd_sender: cmd_sender.clone(),
+            settings: RwLock::new(settings),
+            rcon: Mutex::new(None),
+            stop_requested: AtomicBool::new(false),
+            restarting: AtomicBool::new(false),
+            config: Mutex::new(None),
+            crashes: Mutex::new(VecDeque::new()),
+            stopped: Notify::new(),
+            wake_listener: Mutex::new(None),
         });
 
         server.clone().spawn_listener(cmd_receiver);
 
-        (server, cmd_sender, stdout_receiver)
+        (server, cmd_sender, event_receiver)
     }
 
     fn spawn_listener(self: Arc<Self>, mut cmd_receiver: mpsc::Receiver<ServerCommand>) {
@@ -37,74 +90,105 @@ impl ServerManager {
             while let Some(cmd) = cmd_receiver.recv().await {
                 match cmd {
                     ServerCommand::Stdin(cmd) => {
-                        self.write_to_stdin(cmd + "\n").await;
+                        if let Err(err) = self.send_stdin(cmd).await {
+                            warn!("Failed to write to Minecraft server stdin: {err}");
+                        }
+                    }
+                    ServerCommand::StartServer { config } => self.start(config).await,
+                    ServerCommand::Stop => {
+                        let server = self.clone();
+                        tokio::spawn(
+                            async move { server.stop(server.settings().stop_timeout).await },
+                        );
                     }
-                    ServerCommand::StartServer { config } => {
-                        if self.running().await {
+                    ServerCommand::Restart { countdown } => {
+                        if !self.running().await || self.restarting.swap(true, Ordering::SeqCst) {
                             continue;
                         }
-                        info!("Minecraft server started");
-                        let child =
-                            match ServerInternal::launch(&config, self.stdout_sender.clone()).await
-                            {
-                                Ok((internal, child)) => {
-                                    *self.internal.lock().await = Some(internal);
-                                    child
-                                }
-                                Err(e) => {
-                                    self.stdout_sender
-                                        .send(format!("Failed to start server: {e}"))
-                                        .expect("Failed sending value over sender");
-                                    continue;
-                                }
-                            };
-
-                        let stdout_sender_clone = self.stdout_sender.clone();
-                        let internal_clone = self.internal.clone();
-
-                        tokio::spawn(async move {
-                            let run_result =
-                                ServerInternal::run(child, stdout_sender_clone.clone()).await;
-
-                            if let Err(err) = run_result {
-                                warn!("Minecraft process wasn't running: {err}");
-                            }
-
-                            let _ = internal_clone.lock().await.take();
-
-                            info!("Minecraft server stopped");
-
-                            stdout_sender_clone
-                                .send(":red_circle: Server stopped".to_string())
-                                .expect("Failed sending value over sender");
-                        });
+                        tokio::spawn(self.clone().restart(countdown));
                     }
                     ServerCommand::Backup => {
-                        if let Err(backup_err) =
-                            self.clone().create_backup(self.stdout_sender.clone()).await
-                        {
-                            if backup_err.1 {
-                                self.enable_save().await;
-                            }
-                            self.stdout_sender
-                                .send(backup_err.0)
+                        if let Err(err) = self.clone().create_backup().await {
+                            warn!("Failed creating server backup: {err}");
+                            self.event_sender
+                                .send(ServerEvent::BackupFailed {
+                                    reason: err.to_string(),
+                                })
                                 .expect("Failed sending value over sender");
                         }
                     }
+                    // Runs here, so nothing starts the server while the worlds are replaced
+                    ServerCommand::Restore { backup } => match self.restore(&backup).await {
+                        Ok(snapshot) => {
+                            info!("Restored backup {backup}");
+                            self.event_sender
+                                .send(ServerEvent::RestoreFinished {
+                                    name: backup,
+                                    snapshot,
+                                })
+                                .expect("Failed sending value over sender");
+                        }
+                        Err(err) => {
+                            warn!("Failed restoring backup {backup}: {err}");
+                            self.event_sender
+                                .send(ServerEvent::RestoreFailed {
+                                    reason: err.to_string(),
+                                })
+                                .expect("Failed sending value over sender");
+                        }
+                    },
                 }
             }
         });
     }
 
-    async fn create_backup(
-        self: Arc<ServerManager>,
-        stdout_sender: broadcast::Sender<String>,
-    ) -> Result<(), (String, bool)> {
+    async fn start(self: &Arc<Self>, config: ServerConfig) {
+        if self.running().await {
+            // Something else started the server before the restart got to it
+            self.restarting.store(false, Ordering::SeqCst);
+            return;
+        }
+        info!("Minecraft server started");
+        // The server binds the port itself
+        self.stop_wake_listener().await;
+        let launched = ServerInternal::launch(&config, self.event_sender.clone()).await;
+        self.restarting.store(false, Ordering::SeqCst);
+        let child = match launched {
+            Ok((internal, child)) => {
+                *self.internal.lock().await = Some(internal);
+                child
+            }
+            Err(e) => {
+                self.event_sender
+                    .send(ServerEvent::StartFailed {
+                        error: e.to_string(),
+                    })
+                    .expect("Failed sending value over sender");
+                self.start_wake_listener().await;
+                return;
+            }
+        };
+        self.stop_requested.store(false, Ordering::SeqCst);
+        *self.config.lock().await = Some(config.clone());
+
+        self.event_sender
+            .send(ServerEvent::Started)
+            .expect("Failed sending value over sender");
+
+        let process = ServerInternal::run(child, self.event_sender.clone());
+        self.clone()
+            .supervise(config, async move { process.await.map(Some) });
+    }
+
+    async fn create_backup(self: Arc<ServerManager>) -> Result<(), BackupError> {
+        let Some(backup_config) = self.settings().backup else {
+            return Err(BackupError::NotConfigured);
+        };
         info!("Starting server backup...");
         let self_clone = self.clone();
         let handle = tokio::spawn(async move {
             self_clone
-                .await_stdout(
+                .await_message(
                     "Automatic saving is now disabled".to_string(),
                     Duration::from_secs(10),
                 )
@@ -113,141 +197,677 @@ impl ServerManager {
         self.write_to_stdin("save-off\n").await;
         let success = handle.await.expect("Failed joining tokio thread");
         if !success {
-            return Err((
-                ":warning: Failed creating server backup, `save-off` did not run successfully."
-                    .to_string(),
-                false,
-            ));
+            return Err(BackupError::SaveOff);
         }
 
-        let self_clone = self.clone();
-        let handle = tokio::spawn(async move {
-            self_clone
-                .await_stdout("Saved the game".to_string(), Duration::from_secs(60))
+        let result = async {
+            let self_clone = self.clone();
+            let handle = tokio::spawn(async move {
+                self_clone
+                    .await_message("Saved the game".to_string(), Duration::from_secs(60))
+                    .await
+            });
+            self.write_to_stdin("save-all\n").await;
+            let success = handle.await.expect("Failed joining tokio thread");
+            if !success {
+                return Err(BackupError::SaveAll);
+            }
+            self.write_backup(&backup_config).await
+        }
+        .await;
+        self.enable_save().await;
+        let (name, size) = result?;
+
+        self.event_sender
+            .send(ServerEvent::BackupFinished { name, size })
+            .expect("Failed sending value over sender");
+        info!("Successfully created server backup");
+
+        if let Some(retention) = backup_config.retention {
+            self.prune_backups(&backup_config, retention).await;
+        }
+        Ok(())
+    }
+
+    /// Deletes the old backups the retention policy doesn't keep.
+    async fn prune_backups(&self, backup_config: &BackupConfig, retention: Retention) {
+        let folder = backup_config.folder.clone();
+        let name = backup_config.name.clone();
+        let result = task::spawn_blocking(move || retention::prune(&folder, &name, &retention))
+            .await
+            .expect("Failed joining prune thread");
+        let event = match result {
+            Ok(pruned) if pruned.deleted.is_empty() => return,
+            Ok(pruned) => {
+                let verb = if retention.dry_run {
+                    "Would delete"
+                } else {
+                    "Deleted"
+                };
+                info!(
+                    "{verb} {} old backups, keeping {}",
+                    pruned.deleted.len(),
+                    pruned.kept
+                );
+                ServerEvent::BackupsPruned {
+                    freed: pruned.deleted.iter().map(|backup| backup.size).sum(),
+                    deleted: pruned
+                        .deleted
+                        .into_iter()
+                        .map(|backup| backup.name)
+                        .collect(),
+                    dry_run: retention.dry_run,
+                }
+            }
+            Err(err) => {
+                warn!("Failed pruning old backups: {err}");
+                ServerEvent::PruneFailed {
+                    reason: err.to_string(),
+                }
+            }
+        };
+        self.event_sender
+            .send(event)
+            .expect("Failed sending value over sender");
+    }
+
+    /// Lists the backups in the backup folder, newest first.
+    pub(crate) async fn backups(&self) -> Result<Vec<Backup>, BackupError> {
+        let Some(backup_config) = self.settings().backup else {
+            return Err(BackupError::NotConfigured);
+        };
+        task::spawn_blocking(move || {
+            retention::list_backups(&backup_config.folder, &backup_config.name)
+                .map_err(|err| BackupError::Read(backup_config.folder, err))
+        })
+        .await
+        .expect("Failed joining backup thread")
+    }
+
+    /// Stops the server, moves its worlds into a snapshot in the backup folder, extracts the backup and
+    /// starts the server again if it was running. Returns the snapshot folder.
+    async fn restore(self: &Arc<Self>, name: &str) -> Result<PathBuf, RestoreError> {
+        let Some(backup_config) = self.settings().backup else {
+            return Err(RestoreError::NotConfigured);
+        };
+        // Only listed backups can be restored, so the name can't point anywhere else
+        let folder = backup_config.folder.clone();
+        let format = backup_config.name.clone();
+        let backup = task::spawn_blocking(move || retention::list_backups(&folder, &format))
+            .await
+            .expect("Failed joining backup thread")
+            .map_err(|err| RestoreError::Read(backup_config.folder.clone(), err))?
+            .into_iter()
+            .find(|backup| backup.name == name)
+            .ok_or_else(|| RestoreError::NotFound(name.to_string()))?;
+        let compression = Compression::from_name(&backup.name)
+            .ok_or_else(|| RestoreError::UnknownFormat(backup.name.clone()))?;
+        // Refused before anything is touched, restoring nothing would make the server generate a new world
+        let path = backup.path.clone();
+        let root = task::spawn_blocking(move || backup::find_root(&path, compression))
+            .await
+            .expect("Failed joining restore thread")?;
+
+        info!("Restoring backup {name}...");
+        self.event_sender
+            .send(ServerEvent::RestoreStarted {
+                name: name.to_string(),
+            })
+            .expect("Failed sending value over sender");
+        let was_running = self.running().await;
+        if was_running {
+            self.stop(self.settings().stop_timeout).await;
+            if self.running().await {
+                return Err(RestoreError::StillRunning);
+            }
+        }
+
+        let snapshot = backup_config
+            .folder
+            .join(DateTime::now().format("'restore_snapshot'_yyyy_MM_dd_HH_mm_ss"));
+        let server_folder = backup_config.server_folder.clone();
+        let snapshot_clone = snapshot.clone();
+        task::spawn_blocking(move || {
+            let worlds = backup::move_worlds(&server_folder, &snapshot_clone)?;
+            info!(
+                "Moved {} worlds into {}",
+                worlds.len(),
+                snapshot_clone.display()
+            );
+            backup::extract(&backup.path, compression, &root, &server_folder).map_err(|err| {
+                RestoreError::Incomplete {
+                    error: Box::new(err),
+                    snapshot: snapshot_clone,
+                }
+            })
+        })
+        .await
+        .expect("Failed joining restore thread")?;
+
+        if was_running {
+            if let Some(config) = self.config.lock().await.clone() {
+                self.start(config).await;
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the backup with the custom backup command, or else into an archive. Returns the name of the
+    /// backup and the size of the archive.
+    async fn write_backup(
+        &self,
+        backup_config: &BackupConfig,
+    ) -> Result<(String, Option<u64>), BackupError> {
+        let server_folder = backup_config.server_folder.to_string_lossy();
+        let backup_folder = backup_config.folder.to_string_lossy();
+        let backup_name = DateTime::now().format(&backup_config.name);
+
+        if let Some(backup_command) = &backup_config.command {
+            // Placeholders are replaced per argument, so paths with spaces stay one argument
+            let backup_command: Vec<String> = backup_command
+                .iter()
+                .map(|arg| {
+                    arg.replace("{BACKUP_FOLDER}", &backup_folder)
+                        .replace("{BACKUP_NAME}", &backup_name)
+                        .replace("{SERVER_FOLDER}", &server_folder)
+                })
+                .collect();
+
+            let output = Command::new(&backup_command[0])
+                .args(&backup_command[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .output()
                 .await
-        });
-        self.write_to_stdin("save-all\n").await;
-        let success = handle.await.expect("Failed joining tokio thread");
-        if !success {
-            return Err((
-                ":warning: Failed creating server backup, `save-all` did not run successfully."
-                    .to_string(),
-                true,
-            ));
+                .map_err(BackupError::CommandFailed)?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(BackupError::CommandExited {
+                    status: output.status,
+                    stderr: stderr.trim().lines().last().unwrap_or_default().to_string(),
+                });
+            }
+            return Ok((backup_name, None));
         }
 
-        let server_folder: String = env::var("SERVER_FOLDER").map_err(|_| {
-            (
-                ":warning: Failed creating server backup, `SERVER_FOLDER` environment variable is not set."
-                    .to_string(),
-                true,
+        let compression = Compression::from_name(&backup_name)
+            .ok_or_else(|| BackupError::UnknownFormat(backup_name.clone()))?;
+        let path = backup_config.folder.join(&backup_name);
+        let server_folder = backup_config.server_folder.clone();
+        let exclude = backup_config.folder.clone();
+        let event_sender = self.event_sender.clone();
+        let archive = task::spawn_blocking(move || {
+            let mut reported = Instant::now();
+            let result = backup::create(
+                &server_folder,
+                &path,
+                compression,
+                &exclude,
+                |bytes, total_bytes| {
+                    if reported.elapsed() >= BACKUP_PROGRESS_INTERVAL {
+                        reported = Instant::now();
+                        event_sender
+                            .send(ServerEvent::BackupProgress { bytes, total_bytes })
+                            .expect("Failed sending value over sender");
+                    }
+                },
             )
-        })?;
+            .and_then(|archive| {
+                backup::verify(&path, compression, &archive)?;
+                Ok(archive)
+            });
+            // Don't leave incomplete or corrupt backups behind
+            if result.is_err() {
+                if let Err(err) = fs::remove_file(&path) {
+                    if err.kind() != io::ErrorKind::NotFound {
+                        warn!("Failed removing failed backup {}: {err}", path.display());
+                    }
+                }
+            }
+            result
+        })
+        .await
+        .expect("Failed joining backup thread")?;
+        info!(
+            "Wrote {} files with {} bytes into {backup_name}, verified it",
+            archive.files, archive.bytes
+        );
+
+        Ok((backup_name, Some(archive.size)))
+    }
+
+    /// Attaches to a server which is already running, either discovered by the PID file EVE writes when
+    /// launching the server or by reaching it over RCON. Returns `false` if no running server was found.
+    pub(crate) async fn attach(self: &Arc<Self>, config: ServerConfig) -> bool {
+        if self.running().await {
+            return false;
+        }
 
-        let backup_folder = env::var("BACKUP_FOLDER").map_err(|_| {
-            (
-                ":warning: Failed creating server backup, `BACKUP_FOLDER` environment variable is not set."
-                    .to_string(),
-                true,
+        let pid = ServerInternal::read_pid_file(&config)
+            .filter(|pid| process_alive(*pid, config.program()));
+        // Without a PID, the RCON connection is used to notice when the server stops
+        let rcon_config = self.settings().rcon;
+        let rcon = match (&rcon_config, pid) {
+            (Some(rcon_config), None) => time::timeout(
+                Duration::from_secs(5),
+                RconClient::connect(&rcon_config.address, &rcon_config.password),
             )
-        })?;
+            .await
+            .ok()
+            .and_then(Result::ok),
+            _ => None,
+        };
 
-        let backup_name = DateTime::now().format(
-            &env::var("BACKUP_NAME").unwrap_or("'backup'_yyyy_MM_dd_HH_mm'.tar.gz'".to_string()),
-        );
+        if pid.is_none() && rcon.is_none() {
+            self.start_wake_listener().await;
+            return false;
+        }
 
-        let backup_command = env::var("BACKUP_COMMAND").ok();
+        *self.internal.lock().await = Some(ServerInternal { stdin: None, pid });
+        self.stop_requested.store(false, Ordering::SeqCst);
+        *self.config.lock().await = Some(config.clone());
 
-        let child_result = if let Some(backup_command) = backup_command {
-            let backup_command = backup_command
-                .replace("{BACKUP_FOLDER}", &backup_folder)
-                .replace("{BACKUP_NAME}", &backup_name)
-                .replace("{SERVER_FOLDER}", &server_folder);
+        info!("Attached to running Minecraft server");
+        if rcon_config.is_none() {
+            warn!("Commands can't be sent to an attached server without RCON");
+        }
+        self.event_sender
+            .send(ServerEvent::Attached {
+                pid,
+                commands_available: rcon_config.is_some(),
+            })
+            .expect("Failed sending value over sender");
 
-            let splitted_command: Vec<&str> = backup_command.split(' ').collect();
+        let process = ServerInternal::follow(config.clone(), pid, rcon, self.event_sender.clone());
+        self.clone().supervise(config, process);
 
-            let mut command = Command::new(splitted_command[0]);
-            command
-                .args(&splitted_command[1..])
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()
-        } else {
-            let mut command = Command::new("tar");
-            command
-                .args([
-                    "-czf",
-                    &format!("{backup_folder}/{backup_name}"),
-                    &server_folder,
-                ])
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()
-        };
+        true
+    }
 
-        child_result
-            .map_err(|err| {
-                (
-                    format!(
-                        ":warning: Failed creating server backup, backup command failed: {err}"
-                    ),
-                    true,
-                )
-            })?
-            .wait()
+    /// Waits for the server process to exit, cleans up and restarts the server if it crashed.
+    fn supervise(
+        self: Arc<Self>,
+        config: ServerConfig,
+        process: impl Future<Output = io::Result<Option<ExitStatus>>> + Send + 'static,
+    ) {
+        tokio::spawn(async move {
+            let run_result = process.await;
+
+            info!("Minecraft server stopped");
+
+            let exit_status = run_result.as_ref().ok().copied().flatten();
+            let restart_delay = match run_result {
+                Ok(Some(exit_status))
+                    if !exit_status.success() && !self.stop_requested.load(Ordering::SeqCst) =>
+                {
+                    self.handle_crash(exit_status).await
+                }
+                Ok(_) => None,
+                Err(err) => {
+                    warn!("Minecraft process wasn't running: {err}");
+                    None
+                }
+            };
+
+            self.event_sender
+                .send(ServerEvent::Stopped { exit_status })
+                .expect("Failed sending value over sender");
+
+            ServerInternal::remove_pid_file(&config);
+            let _ = self.internal.lock().await.take();
+            let _ = self.rcon.lock().await.take();
+            self.stopped.notify_waiters();
+
+            if let Some(delay) = restart_delay {
+                time::sleep(delay).await;
+                self.cmd_sender
+                    .send(ServerCommand::StartServer { config })
+                    .await
+                    .expect("Failed sending value over sender");
+            } else if !self.restarting.load(Ordering::SeqCst) {
+                self.start_wake_listener().await;
+            }
+        });
+    }
+
+    /// Announces the restart in game for every step of the countdown, stops the server and starts it again
+    /// once the process exited. The restart is called off if the process changed during the countdown, e.g.
+    /// because it crashed and was restarted already.
+    async fn restart(self: Arc<Self>, mut countdown: Vec<Duration>) {
+        countdown.sort_unstable_by(|a, b| b.cmp(a));
+        countdown.dedup();
+        info!("Restarting Minecraft server...");
+
+        let process = self.process().await;
+        if process.is_none() {
+            self.restarting.store(false, Ordering::SeqCst);
+            return;
+        }
+        for (i, remaining) in countdown.iter().enumerate() {
+            self.write_to_stdin(
+                format!(
+                    r##"tellraw @a {{"text":"Server restarting in {}","color":"gold"}}"##,
+                    format_duration(*remaining)
+                ) + "\n",
+            )
+            .await;
+            let next = countdown.get(i + 1).copied().unwrap_or_default();
+            time::sleep(*remaining - next).await;
+            if self.process().await != process {
+                info!("Minecraft server stopped or changed during the restart countdown");
+                self.restarting.store(false, Ordering::SeqCst);
+                return;
+            }
+        }
+
+        self.stop(self.settings().stop_timeout).await;
+        let config = self.config.lock().await.clone();
+        match config {
+            // Starting the server resets `restarting`
+            Some(config) if !self.running().await => self
+                .cmd_sender
+                .send(ServerCommand::StartServer { config })
+                .await
+                .expect("Failed sending value over sender"),
+            _ => self.restarting.store(false, Ordering::SeqCst),
+        }
+    }
+
+    /// The PID of the running server, `Some(None)` if it was attached without one and `None` if it's not
+    /// running.
+    async fn process(&self) -> Option<Option<u32>> {
+        self.internal
+            .lock()
             .await
-            .map_err(|err| {
-                (
-                    format!(
-                        ":warning: Failed creating server backup, backup command failed: {err}"
-                    ),
-                    true,
-                )
-            })?;
+            .as_ref()
+            .map(|internal| internal.pid)
+    }
+
+    /// Returns the delay after which a crashed server should be restarted, or `None` if it crashed
+    /// too often within the configured window.
+    async fn handle_crash(&self, exit_status: ExitStatus) -> Option<Duration> {
+        let policy = self.settings().restart_policy;
+        if policy.max_crashes == 0 {
+            warn!("Minecraft server crashed ({exit_status})");
+            self.event_sender
+                .send(ServerEvent::Crashed {
+                    exit_status,
+                    restart: None,
+                })
+                .expect("Failed sending value over sender");
+            return None;
+        }
+
+        let attempt = {
+            let mut crashes = self.crashes.lock().await;
+            let now = Instant::now();
+            crashes.retain(|crash| now.duration_since(*crash) < policy.window);
+            crashes.push_back(now);
+            crashes.len() as u32
+        };
+
+        if attempt > policy.max_crashes {
+            error!("Minecraft server crashed {attempt} times, giving up on automatic restarts");
+            self.crashes.lock().await.clear();
+            self.event_sender
+                .send(ServerEvent::RestartsExhausted {
+                    exit_status,
+                    crashes: attempt,
+                    window: policy.window,
+                })
+                .expect("Failed sending value over sender");
+            return None;
+        }
 
-        stdout_sender
-            .send(format!(
-                ":white_check_mark: Successfully created server backup `{backup_name}`"
-            ))
+        let delay = policy.delay(attempt);
+        warn!(
+            "Minecraft server crashed ({exit_status}), restarting in {}s",
+            delay.as_secs()
+        );
+        self.event_sender
+            .send(ServerEvent::Crashed {
+                exit_status,
+                restart: Some(CrashRestart {
+                    delay,
+                    attempt,
+                    max_crashes: policy.max_crashes,
+                }),
+            })
             .expect("Failed sending value over sender");
-        self.enable_save().await;
-        info!("Successfully created server backup");
 
-        Ok(())
+        Some(delay)
+    }
+
+    /// Runs a command on the server. With RCON configured, the command is sent over RCON and its output
+    /// returned. Otherwise, it's written to stdin.
+    pub(crate) async fn send_command(&self, cmd: String) -> Result<Option<String>, RconError> {
+        if cmd.trim().eq_ignore_ascii_case("stop") {
+            self.stop_requested.store(true, Ordering::SeqCst);
+        }
+
+        match self.settings().rcon {
+            Some(rcon_config) => self.rcon_cmd(&rcon_config, &cmd).await.map(Some),
+            None => {
+                self.write_to_stdin(cmd + "\n").await;
+                Ok(None)
+            }
+        }
+    }
+
+    pub(crate) fn rcon_enabled(&self) -> bool {
+        self.settings().rcon.is_some()
+    }
+
+    async fn rcon_cmd(&self, rcon_config: &RconConfig, cmd: &str) -> Result<String, RconError> {
+        let mut rcon = self.rcon.lock().await;
+
+        let result = time::timeout(Duration::from_secs(10), async {
+            if rcon.is_none() {
+                *rcon =
+                    Some(RconClient::connect(&rcon_config.address, &rcon_config.password).await?);
+            }
+            rcon.as_mut()
+                .expect("RCON client was just connected")
+                .cmd(cmd)
+                .await
+        })
+        .await
+        .unwrap_or(Err(RconError::Timeout));
+
+        if let Err(err) = &result {
+            warn!("Failed running command over RCON: {err}");
+            // Reconnect with the next command
+            *rcon = None;
+        }
+        result
     }
 
     async fn enable_save(&self) {
         self.write_to_stdin("save-on\n").await;
     }
 
+    /// Sends a command to the server like it was typed into its console.
+    pub(crate) async fn send_stdin(&self, cmd: String) -> Result<(), StdinError> {
+        if cmd.trim().eq_ignore_ascii_case("stop") {
+            self.stop_requested.store(true, Ordering::SeqCst);
+        }
+        self.try_write_to_stdin(cmd + "\n").await
+    }
+
     async fn write_to_stdin<B: AsRef<[u8]>>(&self, bytes: B) {
+        if let Err(err) = self.try_write_to_stdin(bytes).await {
+            warn!("Failed to write to Minecraft server stdin: {err}");
+        }
+    }
+
+    async fn try_write_to_stdin<B: AsRef<[u8]>>(&self, bytes: B) -> Result<(), StdinError> {
         let bytes = bytes.as_ref();
         let mut internal = self.internal.lock().await;
-        if let Some(internal) = &mut *internal {
-            if let Err(e) = internal.stdin.write_all(bytes).await {
-                warn!("Failed to write to Minecraft server stdin: {e}");
+        match &mut *internal {
+            Some(ServerInternal {
+                stdin: Some(stdin), ..
+            }) => Ok(stdin.write_all(bytes).await?),
+            // Attached servers have no stdin, their commands go over RCON
+            Some(ServerInternal { stdin: None, .. }) => {
+                drop(internal);
+                let Some(rcon_config) = self.settings().rcon else {
+                    return Err(StdinError::NoRcon);
+                };
+                for cmd in String::from_utf8_lossy(bytes).lines() {
+                    let output = self.rcon_cmd(&rcon_config, cmd).await?;
+                    for line in output.lines() {
+                        let _ = self
+                            .event_sender
+                            .send(ServerEvent::ConsoleLine(line.to_string()));
+                    }
+                }
+                Ok(())
             }
+            None => Err(StdinError::NotRunning),
         }
     }
 
-    async fn await_stdout(&self, expected_msg: String, timeout: Duration) -> bool {
-        let mut stdout_receiver = self.stdout_sender.subscribe();
+    async fn await_message(&self, expected_msg: String, timeout: Duration) -> bool {
+        let mut event_receiver = self.event_sender.subscribe();
 
-        let message_handle = tokio::spawn(async move {
-            while let Ok(msg) = stdout_receiver.recv().await {
-                if msg.contains(&expected_msg) {
-                    break;
+        let seen = async move {
+            loop {
+                match event_receiver.recv().await {
+                    Ok(ServerEvent::ConsoleLine(line)) => {
+                        let message =
+                            LogLine::parse(&line).map_or(line.as_str(), |line| line.message);
+                        if message == expected_msg {
+                            return true;
+                        }
+                    }
+                    Ok(_) => {}
+                    // The line might be among the skipped ones, in which case this times out
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Skipped {skipped} server events while waiting for `{expected_msg}`");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return false,
                 }
             }
-        });
+        };
+        time::timeout(timeout, seen).await.unwrap_or(false)
+    }
+
+    /// Stops the server and waits for it to exit. If it didn't exit within `grace_period`, the process
+    /// gets terminated with `SIGTERM` and, if that didn't help either, killed with `SIGKILL`.
+    pub(crate) async fn stop(&self, grace_period: Duration) {
+        let stopped = self.stopped.notified();
+        if !self.running().await {
+            return;
+        }
+
+        info!("Stopping Minecraft server...");
+        self.stop_requested.store(true, Ordering::SeqCst);
+        self.write_to_stdin("stop\n").await;
+
+        if time::timeout(grace_period, stopped).await.is_ok() {
+            return;
+        }
+
+        for (signal, name) in [(libc::SIGTERM, "SIGTERM"), (libc::SIGKILL, "SIGKILL")] {
+            let stopped = self.stopped.notified();
+            if !self.running().await {
+                return;
+            }
+
+            warn!(
+                "Minecraft server didn't stop within {}s, sending {name}",
+                grace_period.as_secs()
+            );
+            self.event_sender
+                .send(ServerEvent::StopEscalated {
+                    signal: name,
+                    grace_period,
+                })
+                .expect("Failed sending value over sender");
+            self.signal(signal).await;
+
+            if time::timeout(grace_period, stopped).await.is_ok() {
+                return;
+            }
+        }
+
+        error!("Minecraft server is still running after SIGKILL");
+        self.event_sender
+            .send(ServerEvent::StopFailed)
+            .expect("Failed sending value over sender");
+    }
+
+    async fn signal(&self, signal: libc::c_int) {
+        let internal = self.internal.lock().await;
+        if let Some(pid) = internal.as_ref().and_then(|internal| internal.pid) {
+            // The server runs in its own process group, signal the whole group so a `java` started by a
+            // launch script stops as well. Fall back to the process for servers launched differently.
+            // SAFETY: `kill` has no memory safety requirements, the pid belongs to our child process
+            if unsafe { libc::kill(-(pid as libc::pid_t), signal) } != 0
+                // SAFETY: See above
+                && unsafe { libc::kill(pid as libc::pid_t, signal) } != 0
+            {
+                warn!(
+                    "Failed to send signal to Minecraft server: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
 
-        let timeout_handle = tokio::spawn(async move { tokio::time::sleep(timeout).await });
+    fn settings(&self) -> ManagerSettings {
+        self.settings
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
 
-        let success = tokio::select! {
-            _ = message_handle => true,
-            _ = timeout_handle => false
+    /// Replaces the settings, e.g. after the config was reloaded. A changed RCON config is used from the
+    /// next command on.
+    pub(crate) async fn reconfigure(&self, settings: ManagerSettings) {
+        let current = self.settings();
+        let rcon_changed = current.rcon != settings.rcon;
+        let wake_changed = current.wake != settings.wake;
+        *self.settings.write().unwrap_or_else(|err| err.into_inner()) = settings;
+        if rcon_changed {
+            *self.rcon.lock().await = None;
+        }
+        if wake_changed {
+            self.stop_wake_listener().await;
+            self.start_wake_listener().await;
+        }
+    }
+
+    /// Listens for players on the game port while the server is offline, if wake on join is enabled.
+    async fn start_wake_listener(&self) {
+        let Some(wake) = self.settings().wake else {
+            return;
         };
-        success
+        let mut listener = self.wake_listener.lock().await;
+        if listener.is_some() || self.running().await {
+            return;
+        }
+        let event_sender = self.event_sender.clone();
+        *listener = Some(tokio::spawn(async move {
+            if let Err(err) = wake::listen(wake, event_sender).await {
+                warn!("Failed listening for players to wake the server: {err}");
+            }
+        }));
+    }
+
+    /// Releases the game port, so the server can bind it.
+    async fn stop_wake_listener(&self) {
+        if let Some(listener) = self.wake_listener.lock().await.take() {
+            listener.abort();
+            // The port is free once the task and its listener are dropped
+            let _ = listener.await;
+        }
+    }
+
+    /// Subscribes to the events of the server.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.event_sender.subscribe()
     }
 
     pub(crate) async fn running(&self) -> bool {
@@ -255,3 +875,13 @@ impl ServerManager {
         running.is_some()
     }
 }
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        1 => "1 second".to_string(),
+        60 => "1 minute".to_string(),
+        secs if secs % 60 == 0 => format!("{} minutes", secs / 60),
+        secs => format!("{secs} seconds"),
+    }
+}
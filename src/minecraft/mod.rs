@@ -1,8 +1,22 @@
+mod args;
+mod backup;
 mod config;
 mod enums;
 mod internal;
 mod manager;
+mod parser;
+mod ping;
+mod rcon;
+mod retention;
+mod wake;
 
-pub(crate) use config::ServerConfig;
+pub(crate) use args::split_args;
+pub(crate) use backup::Compression;
+pub(crate) use config::{
+    BackupConfig, JvmPreset, Launch, ManagerSettings, RconConfig, RestartPolicy, ServerConfig,
+    WakeConfig,
+};
 pub(crate) use enums::*;
 pub(crate) use manager::ServerManager;
+pub(crate) use ping::{ping, strip_formatting};
+pub(crate) use retention::{Backup, Retention};
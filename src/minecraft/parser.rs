@@ -0,0 +1,377 @@
+use std::time::Duration;
+
+const LEVELS: [&str; 6] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL"];
+
+/// Phrases which follow the player name in vanilla death messages.
+const DEATH_PHRASES: [&str; 17] = [
+    "was ",
+    "walked into ",
+    "drowned",
+    "died",
+    "experienced kinetic energy",
+    "blew up",
+    "hit the ground too hard",
+    "fell ",
+    "went up in flames",
+    "burned to death",
+    "went off with a bang",
+    "tried to swim in lava",
+    "discovered the floor was lava",
+    "suffocated in a wall",
+    "starved to death",
+    "froze to death",
+    "withered away",
+];
+
+/// A line of the server console, split into its parts.
+///
+/// Supports the vanilla/Fabric format `[12:34:56] [Server thread/INFO]: ...`, the Paper/Spigot format
+/// `[12:34:56 INFO]: ...` and the Forge/NeoForge format
+/// `[18Jan2024 12:34:56.789] [Server thread/INFO] [net.minecraft.server.MinecraftServer/]: ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LogLine<'a> {
+    pub(crate) timestamp: &'a str,
+    pub(crate) thread: Option<&'a str>,
+    pub(crate) level: &'a str,
+    pub(crate) logger: Option<&'a str>,
+    pub(crate) message: &'a str,
+}
+
+/// Noteworthy things happening on the server, recognized from the console output.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum LogEvent {
+    Joined {
+        player: String,
+    },
+    Left {
+        player: String,
+    },
+    Chat {
+        player: String,
+        message: String,
+    },
+    /// `message` is the whole death message, including the player name.
+    Death {
+        player: String,
+        message: String,
+    },
+    Advancement {
+        player: String,
+        advancement: String,
+    },
+    /// The server finished loading.
+    Done {
+        startup: Duration,
+    },
+    /// `Can't keep up!` warning of an overloaded server.
+    Lagging {
+        behind: Duration,
+        ticks: u32,
+    },
+    Stopping,
+}
+
+impl<'a> LogLine<'a> {
+    /// Parses a console line. Returns `None` for lines which aren't log messages, like stack traces.
+    pub(crate) fn parse(line: &'a str) -> Option<Self> {
+        let rest = line.strip_prefix('[')?;
+        let (first, rest) = rest.split_once(']')?;
+
+        // Paper/Spigot: [12:34:56 INFO]
+        if let Some((timestamp, level)) = first.rsplit_once(' ') {
+            if LEVELS.contains(&level) {
+                let (logger, message) = split_logger(rest)?;
+                return Some(LogLine {
+                    timestamp,
+                    thread: None,
+                    level,
+                    logger,
+                    message,
+                });
+            }
+        }
+
+        // Vanilla/Forge: [12:34:56] [Server thread/INFO]
+        let rest = rest.strip_prefix(" [")?;
+        let (thread_level, rest) = rest.split_once(']')?;
+        let (thread, level) = thread_level.rsplit_once('/')?;
+        if !LEVELS.contains(&level) {
+            return None;
+        }
+        let (logger, message) = split_logger(rest)?;
+
+        Some(LogLine {
+            timestamp: first,
+            thread: Some(thread),
+            level,
+            logger,
+            message,
+        })
+    }
+
+    /// Recognizes the event this line stands for.
+    pub(crate) fn event(&self) -> Option<LogEvent> {
+        let message = self.message;
+
+        if self.level == "WARN" {
+            return parse_lagging(message);
+        }
+        if self.level != "INFO" {
+            return None;
+        }
+
+        if let Some(event) = parse_chat(message) {
+            return Some(event);
+        }
+        if let Some(startup) = message
+            .strip_prefix("Done (")
+            .and_then(|rest| rest.split_once("s)! For help, type \"help\""))
+            .and_then(|(secs, _)| Duration::try_from_secs_f64(secs.parse().ok()?).ok())
+        {
+            return Some(LogEvent::Done { startup });
+        }
+        if message == "Stopping the server" {
+            return Some(LogEvent::Stopping);
+        }
+
+        let (player, rest) = message.split_once(' ')?;
+        if !is_player_name(player) {
+            return None;
+        }
+        let player = player.to_string();
+
+        if rest == "joined the game" || rest.starts_with("joined the game (formerly known as ") {
+            return Some(LogEvent::Joined { player });
+        }
+        if rest == "left the game" {
+            return Some(LogEvent::Left { player });
+        }
+        for prefix in [
+            "has made the advancement [",
+            "has completed the challenge [",
+            "has reached the goal [",
+        ] {
+            if let Some(advancement) = rest
+                .strip_prefix(prefix)
+                .and_then(|advancement| advancement.strip_suffix(']'))
+            {
+                return Some(LogEvent::Advancement {
+                    player,
+                    advancement: advancement.to_string(),
+                });
+            }
+        }
+        if DEATH_PHRASES.iter().any(|phrase| rest.starts_with(phrase)) {
+            return Some(LogEvent::Death {
+                player,
+                message: message.to_string(),
+            });
+        }
+
+        None
+    }
+}
+
+/// Splits an optional ` [logger]` off the rest of the line and returns it together with the message.
+fn split_logger(rest: &str) -> Option<(Option<&str>, &str)> {
+    if let Some(message) = rest.strip_prefix(": ") {
+        return Some((None, message));
+    }
+    if rest == ":" {
+        return Some((None, ""));
+    }
+    let rest = rest.strip_prefix(" [")?;
+    let (logger, rest) = rest.split_once("]: ")?;
+    Some((Some(logger.trim_end_matches('/')), rest))
+}
+
+/// Parses `<Player> message`, optionally prefixed with `[Not Secure] ` for unsigned chat messages.
+fn parse_chat(message: &str) -> Option<LogEvent> {
+    let message = message.strip_prefix("[Not Secure] ").unwrap_or(message);
+    let rest = message.strip_prefix('<')?;
+    let (player, message) = rest.split_once("> ")?;
+    if player.is_empty() || player.contains(['<', '>']) {
+        return None;
+    }
+    Some(LogEvent::Chat {
+        player: player.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// Parses `Can't keep up! Is the server overloaded? Running 2003ms or 40 ticks behind`.
+fn parse_lagging(message: &str) -> Option<LogEvent> {
+    let rest = message.strip_prefix("Can't keep up! Is the server overloaded? Running ")?;
+    let (behind, rest) = rest.split_once("ms or ")?;
+    let ticks = rest.strip_suffix(" ticks behind")?;
+    Some(LogEvent::Lagging {
+        behind: Duration::from_millis(behind.parse().ok()?),
+        ticks: ticks.parse().ok()?,
+    })
+}
+
+/// Java edition names are up to 16 letters, digits and underscores. Bedrock players joining
+/// through Geyser/Floodgate are prefixed with a `.` by default.
+pub(super) fn is_player_name(name: &str) -> bool {
+    let name = name.strip_prefix('.').unwrap_or(name);
+    (1..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogEvent, LogLine};
+    use std::time::Duration;
+
+    /// Checks every case of a fixture, see the fixture files for the format.
+    fn check_fixture(name: &str, fixture: &str) {
+        let cases = fixture
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        for (i, case) in cases {
+            let (expected, line) = case
+                .split_once(" | ")
+                .unwrap_or_else(|| panic!("{name}:{}: invalid case", i + 1));
+            let parsed = LogLine::parse(line);
+
+            if expected == "!" {
+                assert_eq!(parsed, None, "{name}:{}: expected no log line", i + 1);
+                continue;
+            }
+
+            let parsed =
+                parsed.unwrap_or_else(|| panic!("{name}:{}: failed parsing `{line}`", i + 1));
+            let expected_event = match expected.split_once(' ') {
+                None if expected == "-" => None,
+                None if expected == "stopping" => Some(LogEvent::Stopping),
+                Some(("joined", player)) => Some(LogEvent::Joined {
+                    player: player.to_string(),
+                }),
+                Some(("left", player)) => Some(LogEvent::Left {
+                    player: player.to_string(),
+                }),
+                Some(("chat", rest)) => {
+                    let (player, message) = rest.split_once(": ").unwrap();
+                    Some(LogEvent::Chat {
+                        player: player.to_string(),
+                        message: message.to_string(),
+                    })
+                }
+                Some(("death", player)) => Some(LogEvent::Death {
+                    player: player.to_string(),
+                    message: parsed.message.to_string(),
+                }),
+                Some(("advancement", rest)) => {
+                    let (player, advancement) = rest.split_once(": ").unwrap();
+                    Some(LogEvent::Advancement {
+                        player: player.to_string(),
+                        advancement: advancement.to_string(),
+                    })
+                }
+                Some(("done", secs)) => Some(LogEvent::Done {
+                    startup: Duration::from_secs_f64(secs.parse().unwrap()),
+                }),
+                Some(("lagging", rest)) => {
+                    let (behind, ticks) = rest.split_once(' ').unwrap();
+                    Some(LogEvent::Lagging {
+                        behind: Duration::from_millis(behind.parse().unwrap()),
+                        ticks: ticks.parse().unwrap(),
+                    })
+                }
+                _ => panic!("{name}:{}: unknown expectation `{expected}`", i + 1),
+            };
+
+            assert_eq!(parsed.event(), expected_event, "{name}:{}: `{line}`", i + 1);
+        }
+    }
+
+    #[test]
+    fn vanilla_fixture() {
+        check_fixture("vanilla.log", include_str!("fixtures/vanilla.log"));
+    }
+
+    #[test]
+    fn paper_fixture() {
+        check_fixture("paper.log", include_str!("fixtures/paper.log"));
+    }
+
+    #[test]
+    fn spigot_fixture() {
+        check_fixture("spigot.log", include_str!("fixtures/spigot.log"));
+    }
+
+    #[test]
+    fn forge_fixture() {
+        check_fixture("forge.log", include_str!("fixtures/forge.log"));
+    }
+
+    #[test]
+    fn fabric_fixture() {
+        check_fixture("fabric.log", include_str!("fixtures/fabric.log"));
+    }
+
+    #[test]
+    fn vanilla_format() {
+        let line =
+            LogLine::parse("[12:01:10] [Server thread/INFO]: Steve joined the game").unwrap();
+        assert_eq!(
+            line,
+            LogLine {
+                timestamp: "12:01:10",
+                thread: Some("Server thread"),
+                level: "INFO",
+                logger: None,
+                message: "Steve joined the game",
+            }
+        );
+    }
+
+    #[test]
+    fn paper_format() {
+        let line = LogLine::parse("[12:02:00 WARN]: Can't keep up!").unwrap();
+        assert_eq!(
+            line,
+            LogLine {
+                timestamp: "12:02:00",
+                thread: None,
+                level: "WARN",
+                logger: None,
+                message: "Can't keep up!",
+            }
+        );
+    }
+
+    #[test]
+    fn forge_format() {
+        let line = LogLine::parse(
+            "[12:00:31] [Server thread/INFO] [minecraft/DedicatedServer]: Done (21.764s)!",
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            LogLine {
+                timestamp: "12:00:31",
+                thread: Some("Server thread"),
+                level: "INFO",
+                logger: Some("minecraft/DedicatedServer"),
+                message: "Done (21.764s)!",
+            }
+        );
+
+        let line = LogLine::parse(
+            "[18Jan2024 12:01:00.001] [Server thread/INFO] [net.minecraft.server.players.PlayerList/]: Herobrine joined the game",
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            LogLine {
+                timestamp: "18Jan2024 12:01:00.001",
+                thread: Some("Server thread"),
+                level: "INFO",
+                logger: Some("net.minecraft.server.players.PlayerList"),
+                message: "Herobrine joined the game",
+            }
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use super::enums::PingError;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Upper limit for the status response, real responses including a server icon stay well below.
+const MAX_PACKET_LENGTH: i32 = 1 << 21;
+/// Strings in the protocol are at most 32767 characters, which are up to 3 bytes each.
+const MAX_STRING_LENGTH: i32 = 32767 * 3;
+
+/// Server information returned by the Server List Ping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PingResponse {
+    pub(crate) version: String,
+    pub(crate) players: u32,
+    pub(crate) max_players: u32,
+    pub(crate) motd: String,
+}
+
+#[derive(Deserialize)]
+struct StatusJson {
+    version: VersionJson,
+    players: PlayersJson,
+    #[serde(default)]
+    description: Option<ChatJson>,
+}
+
+#[derive(Deserialize)]
+struct VersionJson {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct PlayersJson {
+    max: u32,
+    online: u32,
+}
+
+/// Chat component used for the MOTD, either a plain string or a component with nested `extra` parts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChatJson {
+    Text(String),
+    Component {
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        extra: Vec<ChatJson>,
+    },
+}
+
+impl ChatJson {
+    fn write_plain(&self, plain: &mut String) {
+        match self {
+            ChatJson::Text(text) => plain.push_str(text),
+            ChatJson::Component { text, extra } => {
+                plain.push_str(text);
+                extra.iter().for_each(|part| part.write_plain(plain));
+            }
+        }
+    }
+}
+
+/// Queries the server status with the Server List Ping protocol.
+pub(crate) async fn ping(address: &str) -> Result<PingResponse, PingError> {
+    let (host, port) = split_address(address)?;
+    let mut stream = TcpStream::connect((host, port)).await?;
+
+    // Handshake with protocol version -1 and next state 1 (status)
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, 0x00);
+    write_varint(&mut handshake, -1);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, 1);
+    write_packet(&mut stream, &handshake).await?;
+
+    // Status request
+    write_packet(&mut stream, &[0x00]).await?;
+
+    let length = read_varint(&mut stream).await?;
+    if !(1..=MAX_PACKET_LENGTH).contains(&length) {
+        return Err(PingError::InvalidResponse(format!(
+            "invalid packet length {length}"
+        )));
+    }
+    let mut packet = vec![0; length as usize];
+    stream.read_exact(&mut packet).await?;
+
+    let mut packet = packet.as_slice();
+    let packet_id = read_varint(&mut packet).await?;
+    if packet_id != 0x00 {
+        return Err(PingError::InvalidResponse(format!(
+            "unexpected packet id {packet_id}"
+        )));
+    }
+    let json_length = read_varint(&mut packet).await?;
+    let json = packet
+        .get(..json_length.max(0) as usize)
+        .ok_or_else(|| PingError::InvalidResponse("truncated status".to_string()))?;
+
+    let status: StatusJson = serde_json::from_slice(json)?;
+
+    let mut motd = String::new();
+    if let Some(description) = status.description {
+        description.write_plain(&mut motd);
+    }
+
+    Ok(PingResponse {
+        version: status.version.name,
+        players: status.players.online,
+        max_players: status.players.max,
+        motd: strip_formatting(&motd),
+    })
+}
+
+/// Splits the address into host and port, which defaults to 25565. IPv6 addresses with a port are written
+/// in brackets, like `[::1]:25565`.
+fn split_address(address: &str) -> Result<(&str, u16), PingError> {
+    let invalid = || PingError::InvalidAddress(address.to_string());
+    let (host, port) = if let Some(bracketed) = address.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']').ok_or_else(invalid)?;
+        match rest {
+            "" => (host, None),
+            rest => (host, Some(rest.strip_prefix(':').ok_or_else(invalid)?)),
+        }
+    } else {
+        match address.split_once(':') {
+            // An IPv6 address without a port
+            Some((_, rest)) if rest.contains(':') => (address, None),
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| invalid())?,
+        None => 25565,
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host, port))
+}
+
+/// Removes Minecraft's `§` formatting codes.
+pub(crate) fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+pub(super) fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+pub(super) fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as i32);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+pub(super) async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> Result<i32, PingError> {
+    let mut value = 0;
+    for i in 0..5 {
+        let byte = reader.read_u8().await?;
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(PingError::InvalidResponse("VarInt is too big".to_string()))
+}
+
+pub(super) async fn read_string<R: AsyncRead + Unpin>(reader: &mut R) -> Result<String, PingError> {
+    let length = read_varint(reader).await?;
+    if !(0..=MAX_STRING_LENGTH).contains(&length) {
+        return Err(PingError::InvalidResponse(format!(
+            "invalid string length {length}"
+        )));
+    }
+    let mut string = vec![0; length as usize];
+    reader.read_exact(&mut string).await?;
+    String::from_utf8(string)
+        .map_err(|_| PingError::InvalidResponse("string isn't valid UTF-8".to_string()))
+}
+
+pub(super) async fn write_packet(stream: &mut TcpStream, data: &[u8]) -> Result<(), PingError> {
+    let mut packet = Vec::with_capacity(data.len() + 5);
+    write_varint(&mut packet, data.len() as i32);
+    packet.extend_from_slice(data);
+    stream.write_all(&packet).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn splits_addresses() {
+        let split = |address| split_address(address).ok();
+        assert_eq!(split("127.0.0.1:25566"), Some(("127.0.0.1", 25566)));
+        assert_eq!(split("mc.example.com"), Some(("mc.example.com", 25565)));
+        assert_eq!(split("[::1]:25566"), Some(("::1", 25566)));
+        assert_eq!(split("[::1]"), Some(("::1", 25565)));
+        assert_eq!(split("::1"), Some(("::1", 25565)));
+        assert_eq!(split("localhost:port"), None);
+        assert_eq!(split("[::1]25566"), None);
+        assert_eq!(split(":25565"), None);
+    }
+
+    #[tokio::test]
+    async fn reads_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let length = read_varint(&mut stream).await.unwrap();
+            let mut handshake = vec![0; length as usize];
+            stream.read_exact(&mut handshake).await.unwrap();
+            let mut handshake = handshake.as_slice();
+            assert_eq!(read_varint(&mut handshake).await.unwrap(), 0x00);
+            assert_eq!(read_varint(&mut handshake).await.unwrap(), -1);
+            assert_eq!(read_string(&mut handshake).await.unwrap(), "127.0.0.1");
+            assert_eq!(handshake.read_u16().await.unwrap(), port);
+            assert_eq!(read_varint(&mut handshake).await.unwrap(), 1);
+            assert!(handshake.is_empty());
+
+            assert_eq!(read_varint(&mut stream).await.unwrap(), 1);
+            assert_eq!(stream.read_u8().await.unwrap(), 0x00);
+
+            // Long enough that the lengths take more than one byte
+            let status = json!({
+                "version": {"name": "1.21.1", "protocol": 767},
+                "players": {"max": 20, "online": 3},
+                "description": {"text": "§aHello ", "extra": ["world", {"text": "!"}]},
+                "favicon": "x".repeat(300),
+            })
+            .to_string();
+            let mut response = Vec::new();
+            write_varint(&mut response, 0x00);
+            write_string(&mut response, &status);
+            write_packet(&mut stream, &response).await.unwrap();
+        });
+
+        let response = ping(&format!("127.0.0.1:{port}")).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(
+            response,
+            PingResponse {
+                version: "1.21.1".to_string(),
+                players: 3,
+                max_players: 20,
+                motd: "Hello world!".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn frames_varints() {
+        for value in [0, 1, 127, 128, 255, 25565, 2_097_151, i32::MAX, -1] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            assert_eq!(read_varint(&mut buf.as_slice()).await.unwrap(), value);
+        }
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, [0xAC, 0x02]);
+        write_varint(&mut buf, -1);
+        assert_eq!(buf[2..], [0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+    }
+}
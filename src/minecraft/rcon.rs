@@ -0,0 +1,213 @@
+use super::enums::RconError;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const PACKET_LOGIN: i32 = 3;
+const PACKET_COMMAND: i32 = 2;
+const PACKET_AUTH_RESPONSE: i32 = 2;
+/// Packet type the server doesn't know. It answers it with an error message, which marks the end of a
+/// response that was split into multiple packets.
+const PACKET_SENTINEL: i32 = 200;
+
+/// Maximum body size the server accepts in a single packet.
+const MAX_COMMAND_LENGTH: usize = 1446;
+/// Upper limit for incoming packets, the server itself sends at most 4096 bytes of payload per packet.
+const MAX_PACKET_LENGTH: i32 = 4096 + 10;
+
+/// Client for the Source RCON protocol which Minecraft uses for remote console access.
+pub(crate) struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+impl RconClient {
+    pub(crate) async fn connect(address: &str, password: &str) -> Result<Self, RconError> {
+        let stream = TcpStream::connect(address).await?;
+        let mut client = RconClient { stream, next_id: 1 };
+
+        let id = client.next_id();
+        client.write_packet(id, PACKET_LOGIN, password).await?;
+
+        loop {
+            let packet = client.read_packet().await?;
+            if packet.kind != PACKET_AUTH_RESPONSE {
+                continue;
+            }
+            if packet.id != id {
+                return Err(RconError::AuthFailed);
+            }
+            return Ok(client);
+        }
+    }
+
+    /// Runs a command on the server and returns its output.
+    pub(crate) async fn cmd(&mut self, command: &str) -> Result<String, RconError> {
+        if command.len() > MAX_COMMAND_LENGTH {
+            return Err(RconError::CommandTooLong(command.len()));
+        }
+
+        let id = self.next_id();
+        let sentinel_id = self.next_id();
+        self.write_packet(id, PACKET_COMMAND, command).await?;
+        self.write_packet(sentinel_id, PACKET_SENTINEL, "").await?;
+
+        let mut response = String::new();
+        loop {
+            let packet = self.read_packet().await?;
+            if packet.id == sentinel_id {
+                return Ok(response);
+            }
+            if packet.id == id {
+                response.push_str(&packet.body);
+            }
+        }
+    }
+
+    /// Waits until the server closes the connection. No command may be sent while waiting.
+    pub(crate) async fn closed(&mut self) {
+        let mut buf = [0; 64];
+        while let Ok(1..) = self.stream.read(&mut buf).await {}
+    }
+
+    fn next_id(&mut self) -> i32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    async fn write_packet(&mut self, id: i32, kind: i32, body: &str) -> Result<(), RconError> {
+        let mut packet = Vec::with_capacity(body.len() + 14);
+        packet.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        self.stream.write_all(&packet).await?;
+        Ok(())
+    }
+
+    async fn read_packet(&mut self) -> Result<Packet, RconError> {
+        let length = self.stream.read_i32_le().await?;
+        if !(10..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(RconError::InvalidPacket(format!("invalid length {length}")));
+        }
+
+        let id = self.stream.read_i32_le().await?;
+        let kind = self.stream.read_i32_le().await?;
+
+        let mut body = vec![0; length as usize - 8];
+        self.stream.read_exact(&mut body).await?;
+        if body.ends_with(&[0, 0]) {
+            body.truncate(body.len() - 2);
+        } else {
+            return Err(RconError::InvalidPacket(
+                "body is not null terminated".to_string(),
+            ));
+        }
+
+        Ok(Packet {
+            id,
+            kind,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const PASSWORD: &str = "secret";
+
+    /// Stands in for the server, answering every command with its name split into two packets.
+    async fn stand_in_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut server = RconClient { stream, next_id: 1 };
+                    while let Ok(packet) = server.read_packet().await {
+                        match packet.kind {
+                            PACKET_LOGIN => {
+                                // Source servers send an empty response before the auth response
+                                server.write_packet(packet.id, 0, "").await.unwrap();
+                                let id = if packet.body == PASSWORD {
+                                    packet.id
+                                } else {
+                                    -1
+                                };
+                                server
+                                    .write_packet(id, PACKET_AUTH_RESPONSE, "")
+                                    .await
+                                    .unwrap();
+                            }
+                            PACKET_COMMAND => {
+                                let first = format!("Output of {} ", packet.body);
+                                server.write_packet(packet.id, 0, &first).await.unwrap();
+                                server
+                                    .write_packet(packet.id, 0, "continued")
+                                    .await
+                                    .unwrap();
+                            }
+                            _ => {
+                                let body = format!("Unknown request {:x}", packet.kind);
+                                server.write_packet(packet.id, 0, &body).await.unwrap();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_password() {
+        let address = stand_in_server().await;
+        assert!(matches!(
+            RconClient::connect(&address, "wrong").await,
+            Err(RconError::AuthFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn joins_split_responses() {
+        let address = stand_in_server().await;
+        let mut client = RconClient::connect(&address, PASSWORD).await.unwrap();
+        assert_eq!(
+            client.cmd("list").await.unwrap(),
+            "Output of list continued"
+        );
+        assert_eq!(
+            client.cmd("time query daytime").await.unwrap(),
+            "Output of time query daytime continued"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_long_commands() {
+        let address = stand_in_server().await;
+        let mut client = RconClient::connect(&address, PASSWORD).await.unwrap();
+        let command = "say ".to_string() + &"a".repeat(MAX_COMMAND_LENGTH);
+        assert!(matches!(
+            client.cmd(&command).await,
+            Err(RconError::CommandTooLong(length)) if length == MAX_COMMAND_LENGTH + 4
+        ));
+        // Nothing was sent, so the connection still works
+        assert_eq!(
+            client.cmd("list").await.unwrap(),
+            "Output of list continued"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use astrolabe::{DateTime, DateUtilities};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Which backups are kept when old ones are pruned. A backup is kept if any of the `keep_*` rules keeps it,
+/// `max_size` then deletes the oldest ones until the rest fits. The newest backup is always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Retention {
+    pub(crate) keep_last: Option<u32>,
+    /// Keeps the newest backup of each of the last `keep_daily` days which have a backup.
+    pub(crate) keep_daily: Option<u32>,
+    pub(crate) keep_weekly: Option<u32>,
+    pub(crate) keep_monthly: Option<u32>,
+    /// Total size of the backups in bytes.
+    pub(crate) max_size: Option<u64>,
+    /// Only reports what would be deleted.
+    pub(crate) dry_run: bool,
+}
+
+impl Retention {
+    fn has_keep_rules(&self) -> bool {
+        self.keep_last.is_some()
+            || self.keep_daily.is_some()
+            || self.keep_weekly.is_some()
+            || self.keep_monthly.is_some()
+    }
+}
+
+/// A backup in the backup folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Backup {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    /// When the backup was created, read from its name.
+    pub(crate) created: DateTime,
+    pub(crate) size: u64,
+}
+
+/// What pruning deleted, or would delete in a dry run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Pruned {
+    pub(crate) deleted: Vec<Backup>,
+    pub(crate) kept: usize,
+}
+
+/// Lists the backups in the folder, newest first. Only files whose name matches the `BACKUP_NAME` format,
+/// i.e. it's the name the format produces for the time parsed from it, count as backups.
+pub(crate) fn list_backups(folder: &Path, name_format: &str) -> io::Result<Vec<Backup>> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let Ok(created) = DateTime::parse(&name, name_format) else {
+            continue;
+        };
+        if created.format(name_format) != name {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        backups.push(Backup {
+            name,
+            path: entry.path(),
+            created,
+            size: metadata.len(),
+        });
+    }
+    backups.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.name.cmp(&a.name)));
+    Ok(backups)
+}
+
+/// Deletes the backups the retention doesn't keep. Deleting continues after an error, the first one is
+/// returned once everything else is deleted.
+pub(super) fn prune(folder: &Path, name_format: &str, retention: &Retention) -> io::Result<Pruned> {
+    let backups = list_backups(folder, name_format)?;
+    let total = backups.len();
+    let deleted = plan(backups, retention);
+
+    let mut result = Ok(());
+    if !retention.dry_run {
+        for backup in &deleted {
+            if let Err(err) = fs::remove_file(&backup.path) {
+                if result.is_ok() {
+                    result = Err(io::Error::new(
+                        err.kind(),
+                        format!("failed deleting {}: {err}", backup.name),
+                    ));
+                }
+            }
+        }
+    }
+    result.map(|()| Pruned {
+        kept: total - deleted.len(),
+        deleted,
+    })
+}
+
+/// Picks the backups to delete, `backups` are sorted newest first.
+fn plan(backups: Vec<Backup>, retention: &Retention) -> Vec<Backup> {
+    const DAY: i64 = 24 * 60 * 60;
+
+    let mut keep: HashSet<usize> = if retention.has_keep_rules() {
+        HashSet::new()
+    } else {
+        (0..backups.len()).collect()
+    };
+    // The backup which was just created
+    if !backups.is_empty() {
+        keep.insert(0);
+    }
+
+    if let Some(last) = retention.keep_last {
+        keep.extend(0..(last as usize).min(backups.len()));
+    }
+    let mut keep_generations = |count: Option<u32>, period: &dyn Fn(&Backup) -> i64| {
+        let Some(count) = count else {
+            return;
+        };
+        let mut periods = HashSet::new();
+        for (i, backup) in backups.iter().enumerate() {
+            if periods.len() == count as usize {
+                break;
+            }
+            // Backups are sorted newest first, so the first one of a period is its newest
+            if periods.insert(period(backup)) {
+                keep.insert(i);
+            }
+        }
+    };
+    keep_generations(retention.keep_daily, &|backup| {
+        backup.created.timestamp().div_euclid(DAY)
+    });
+    // Weeks start on Monday, 1970-01-01 was a Thursday
+    keep_generations(retention.keep_weekly, &|backup| {
+        (backup.created.timestamp().div_euclid(DAY) + 3).div_euclid(7)
+    });
+    keep_generations(retention.keep_monthly, &|backup| {
+        i64::from(backup.created.year()) * 12 + i64::from(backup.created.month())
+    });
+
+    if let Some(max_size) = retention.max_size {
+        let mut size = 0;
+        for (i, backup) in backups.iter().enumerate() {
+            if !keep.contains(&i) {
+                continue;
+            }
+            size += backup.size;
+            if size > max_size && i != 0 {
+                keep.remove(&i);
+            }
+        }
+    }
+
+    backups
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !keep.contains(i))
+        .map(|(_, backup)| backup)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    /// Monday, 2024-01-01 00:00 UTC
+    const MONDAY: i64 = 1_704_067_200;
+
+    /// A backup every `interval` going back from `start`, newest first.
+    fn backups(start: i64, interval: i64, count: usize) -> Vec<Backup> {
+        (0..count as i64)
+            .map(|i| {
+                let created = start - i * interval;
+                Backup {
+                    name: created.to_string(),
+                    path: PathBuf::from(created.to_string()),
+                    created: DateTime::from_timestamp(created).unwrap(),
+                    size: 100,
+                }
+            })
+            .collect()
+    }
+
+    fn kept(backups: &[Backup], retention: Retention) -> Vec<i64> {
+        let deleted = plan(backups.to_vec(), &retention);
+        backups
+            .iter()
+            .filter(|backup| !deleted.contains(backup))
+            .map(|backup| backup.created.timestamp())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_last() {
+        let backups = backups(MONDAY, HOUR, 5);
+        let retention = Retention {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(kept(&backups, retention), vec![MONDAY, MONDAY - HOUR]);
+    }
+
+    #[test]
+    fn keeps_newest_of_each_day() {
+        // Every 6 hours for 3 days, starting on Monday 18:00
+        let backups = backups(MONDAY + 18 * HOUR, 6 * HOUR, 12);
+        let retention = Retention {
+            keep_daily: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            kept(&backups, retention),
+            vec![MONDAY + 18 * HOUR, MONDAY - 6 * HOUR]
+        );
+    }
+
+    #[test]
+    fn combines_generations() {
+        // Daily for 40 days, ending on a Monday
+        let backups = backups(MONDAY, 24 * HOUR, 40);
+        let retention = Retention {
+            keep_last: Some(1),
+            keep_weekly: Some(3),
+            keep_monthly: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            kept(&backups, retention),
+            vec![
+                // Newest, also the newest of this week and of January
+                MONDAY,
+                // Sundays, the newest backups of the two weeks before
+                MONDAY - 24 * HOUR,
+                MONDAY - 8 * 24 * HOUR,
+            ]
+        );
+    }
+
+    #[test]
+    fn caps_size() {
+        let backups = backups(MONDAY, HOUR, 5);
+        let retention = Retention {
+            max_size: Some(250),
+            ..Default::default()
+        };
+        assert_eq!(kept(&backups, retention), vec![MONDAY, MONDAY - HOUR]);
+
+        // The newest backup is kept even if it's too large on its own
+        let retention = Retention {
+            max_size: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(kept(&backups, retention), vec![MONDAY]);
+    }
+
+    #[test]
+    fn only_lists_matching_names() {
+        let folder = std::env::temp_dir().join(format!("eve-retention-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        for name in [
+            "backup_2024_01_01_05_00.tar.gz",
+            "backup_2024_01_02_05_00.tar.gz",
+            "backup_2024_01_02_05_00.tar.gz.part",
+            "world.tar.gz",
+            "notes.txt",
+        ] {
+            fs::write(folder.join(name), "").unwrap();
+        }
+        let names: Vec<String> = list_backups(&folder, "'backup'_yyyy_MM_dd_HH_mm'.tar.gz'")
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect();
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                "backup_2024_01_02_05_00.tar.gz",
+                "backup_2024_01_01_05_00.tar.gz"
+            ]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use super::{
+    config::WakeConfig,
+    enums::{PingError, ServerEvent},
+    parser::is_player_name,
+    ping::{read_string, read_varint, write_packet, write_string, write_varint},
+};
+use log::{debug, info};
+use serde_json::json;
+use std::io;
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    time::{self, Duration},
+};
+
+/// Client packets before the login are tiny, anything bigger isn't a Minecraft client.
+const MAX_PACKET_LENGTH: i32 = 4096;
+
+/// Listens on the game port while the server is offline. The Server List Ping is answered with the
+/// configured MOTD and players trying to join are kicked with the kick message and reported with a
+/// [`ServerEvent::WakeRequested`], so the server can be started for them.
+pub(super) async fn listen(
+    config: WakeConfig,
+    event_sender: broadcast::Sender<ServerEvent>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(&config.address).await?;
+    info!(
+        "Listening for players on {} to wake the server",
+        config.address
+    );
+
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                debug!("Failed accepting connection: {err}");
+                continue;
+            }
+        };
+        let config = config.clone();
+        let event_sender = event_sender.clone();
+        tokio::spawn(async move {
+            let result = time::timeout(
+                Duration::from_secs(10),
+                handle(stream, &config, &event_sender),
+            )
+            .await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => debug!("Invalid connection from {address}: {err}"),
+                Err(_) => debug!("Connection from {address} timed out"),
+            }
+        });
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    config: &WakeConfig,
+    event_sender: &broadcast::Sender<ServerEvent>,
+) -> Result<(), PingError> {
+    // Handshake: protocol version, server address, port and the next state
+    let handshake = read_packet(&mut stream).await?;
+    let mut handshake = handshake.as_slice();
+    expect_packet_id(&mut handshake, 0x00).await?;
+    let protocol = read_varint(&mut handshake).await?;
+    read_string(&mut handshake).await?;
+    handshake.read_u16().await?;
+    let next_state = read_varint(&mut handshake).await?;
+
+    match next_state {
+        // Status request, answered with the MOTD, followed by an optional ping
+        1 => {
+            let request = read_packet(&mut stream).await?;
+            expect_packet_id(&mut request.as_slice(), 0x00).await?;
+
+            let status = json!({
+                "version": { "name": "Sleeping", "protocol": protocol },
+                "players": { "max": 0, "online": 0 },
+                "description": { "text": config.motd },
+            });
+            let mut response = Vec::new();
+            write_varint(&mut response, 0x00);
+            write_string(&mut response, &status.to_string());
+            write_packet(&mut stream, &response).await?;
+
+            if let Ok(ping) = read_packet(&mut stream).await {
+                // The pong echoes the ping's payload
+                write_packet(&mut stream, &ping).await?;
+            }
+        }
+        // Login start with the player name, answered with a disconnect
+        2 => {
+            let login = read_packet(&mut stream).await?;
+            let mut login = login.as_slice();
+            expect_packet_id(&mut login, 0x00).await?;
+            let player = read_string(&mut login).await?;
+            // Anyone can connect, the name ends up in Discord
+            if !is_player_name(&player) {
+                return Err(PingError::InvalidResponse(
+                    "invalid player name".to_string(),
+                ));
+            }
+
+            let reason = json!({ "text": config.kick_message });
+            let mut disconnect = Vec::new();
+            write_varint(&mut disconnect, 0x00);
+            write_string(&mut disconnect, &reason.to_string());
+            write_packet(&mut stream, &disconnect).await?;
+
+            info!("{player} tried to join, waking the server");
+            event_sender
+                .send(ServerEvent::WakeRequested { player })
+                .expect("Failed sending value over sender");
+        }
+        state => {
+            return Err(PingError::InvalidResponse(format!(
+                "unsupported next state {state}"
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+async fn read_packet(stream: &mut TcpStream) -> Result<Vec<u8>, PingError> {
+    let length = read_varint(stream).await?;
+    if !(1..=MAX_PACKET_LENGTH).contains(&length) {
+        return Err(PingError::InvalidResponse(format!(
+            "invalid packet length {length}"
+        )));
+    }
+    let mut packet = vec![0; length as usize];
+    stream.read_exact(&mut packet).await?;
+    Ok(packet)
+}
+
+async fn expect_packet_id(packet: &mut &[u8], id: i32) -> Result<(), PingError> {
+    let packet_id = read_varint(packet).await?;
+    if packet_id != id {
+        return Err(PingError::InvalidResponse(format!(
+            "unexpected packet id {packet_id}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minecraft::ping::{self, PingResponse};
+
+    /// Accepts one connection on a local socket and handles it with the config.
+    async fn serve() -> (
+        String,
+        broadcast::Receiver<ServerEvent>,
+        tokio::task::JoinHandle<Result<(), PingError>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (event_sender, events) = broadcast::channel(16);
+        let config = WakeConfig::new(
+            address.clone(),
+            "Join to start".to_string(),
+            "Starting, join again in a minute".to_string(),
+        );
+        let handler = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle(stream, &config, &event_sender).await
+        });
+        (address, events, handler)
+    }
+
+    #[tokio::test]
+    async fn answers_status_with_motd() {
+        let (address, mut events, handler) = serve().await;
+
+        let response = ping::ping(&address).await.unwrap();
+        handler.await.unwrap().unwrap();
+        assert_eq!(
+            response,
+            PingResponse {
+                version: "Sleeping".to_string(),
+                players: 0,
+                max_players: 0,
+                motd: "Join to start".to_string(),
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    /// Connects like a client joining as the player.
+    async fn join(address: &str, player: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, 0x00);
+        write_varint(&mut handshake, 767);
+        write_string(&mut handshake, "127.0.0.1");
+        handshake.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut handshake, 2);
+        write_packet(&mut stream, &handshake).await.unwrap();
+        let mut login = Vec::new();
+        write_varint(&mut login, 0x00);
+        write_string(&mut login, player);
+        write_packet(&mut stream, &login).await.unwrap();
+        stream
+    }
+
+    #[tokio::test]
+    async fn kicks_and_reports_joining_players() {
+        let (address, mut events, handler) = serve().await;
+        let mut stream = join(&address, "Steve_1").await;
+
+        let disconnect = read_packet(&mut stream).await.unwrap();
+        let mut disconnect = disconnect.as_slice();
+        expect_packet_id(&mut disconnect, 0x00).await.unwrap();
+        let reason: serde_json::Value =
+            serde_json::from_str(&read_string(&mut disconnect).await.unwrap()).unwrap();
+        assert_eq!(
+            reason,
+            json!({ "text": "Starting, join again in a minute" })
+        );
+
+        handler.await.unwrap().unwrap();
+        match events.try_recv() {
+            Ok(ServerEvent::WakeRequested { player }) => assert_eq!(player, "Steve_1"),
+            other => panic!("expected a wake request, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_player_names() {
+        for player in ["@everyone", "", &"a".repeat(17), "<@123456789>"] {
+            let (address, mut events, handler) = serve().await;
+            let _stream = join(&address, player).await;
+
+            assert!(handler.await.unwrap().is_err());
+            assert!(events.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_packets() {
+        let (address, mut events, handler) = serve().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+
+        let mut length = Vec::new();
+        write_varint(&mut length, MAX_PACKET_LENGTH + 1);
+        tokio::io::AsyncWriteExt::write_all(&mut stream, &length)
+            .await
+            .unwrap();
+
+        assert!(handler.await.unwrap().is_err());
+        assert!(events.try_recv().is_err());
+    }
+}
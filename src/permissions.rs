@@ -0,0 +1,244 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+use twilight_model::{
+    guild::{PartialMember, Permissions as GuildPermissions},
+    id::{marker::UserMarker, Id},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Level {
+    Everyone,
+    Moderator,
+    Admin,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Everyone => "everyone",
+            Level::Moderator => "moderator",
+            Level::Admin => "admin",
+        })
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.trim().to_lowercase().as_str() {
+            "everyone" => Ok(Level::Everyone),
+            "moderator" => Ok(Level::Moderator),
+            "admin" => Ok(Level::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Decides who is allowed to use which command. Users and roles are mapped to a [`Level`], every command
+/// requires a level. Members with the Discord administrator permission are always admins.
+#[derive(Debug)]
+pub(crate) struct Permissions {
+    moderators: Vec<u64>,
+    admins: Vec<u64>,
+    commands: HashMap<String, Level>,
+    send_allow: Vec<String>,
+    send_deny: Vec<String>,
+}
+
+impl Permissions {
+    pub(crate) fn new(
+        moderators: Vec<u64>,
+        admins: Vec<u64>,
+        commands: HashMap<String, Level>,
+        send_allow: Vec<String>,
+        send_deny: Vec<String>,
+    ) -> Self {
+        let normalize = |patterns: Vec<String>| {
+            patterns
+                .iter()
+                .map(|pattern| normalize_command(pattern))
+                .filter(|pattern| !pattern.is_empty())
+                .collect()
+        };
+        Self {
+            moderators,
+            admins,
+            commands,
+            send_allow: normalize(send_allow),
+            send_deny: normalize(send_deny),
+        }
+    }
+
+    pub(crate) fn moderators(&self) -> &[u64] {
+        &self.moderators
+    }
+
+    pub(crate) fn admins(&self) -> &[u64] {
+        &self.admins
+    }
+
+    /// The overridden levels, sorted by command.
+    pub(crate) fn commands(&self) -> Vec<(&str, Level)> {
+        let mut commands: Vec<_> = self
+            .commands
+            .iter()
+            .map(|(command, level)| (command.as_str(), *level))
+            .collect();
+        commands.sort();
+        commands
+    }
+
+    pub(crate) fn send_allow(&self) -> &[String] {
+        &self.send_allow
+    }
+
+    pub(crate) fn send_deny(&self) -> &[String] {
+        &self.send_deny
+    }
+
+    /// Returns the level of the user, based on their ID and, if used in a guild, their roles.
+    pub(crate) fn level(&self, user_id: Id<UserMarker>, member: Option<&PartialMember>) -> Level {
+        let has = |ids: &[u64]| {
+            ids.contains(&user_id.get())
+                || member
+                    .is_some_and(|member| member.roles.iter().any(|role| ids.contains(&role.get())))
+        };
+
+        let administrator = member
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| permissions.contains(GuildPermissions::ADMINISTRATOR));
+
+        if administrator || has(&self.admins) {
+            Level::Admin
+        } else if has(&self.moderators) {
+            Level::Moderator
+        } else {
+            Level::Everyone
+        }
+    }
+
+    /// Returns the level required to use the slash command.
+    pub(crate) fn required(&self, command: &str) -> Level {
+        if let Some(level) = self.commands.get(command) {
+            return *level;
+        }
+        match command {
+            "status" | "say" => Level::Everyone,
+            "send" | "restore" => Level::Admin,
+            _ => Level::Moderator,
+        }
+    }
+
+    /// Checks the command against the `SEND_ALLOW` and `SEND_DENY` patterns. Deny patterns take precedence,
+    /// if allow patterns are set, the command has to match one of them.
+    ///
+    /// Only the command itself is checked, commands nested in others like `execute as @a run op Notch`
+    /// aren't caught by `op`. Deny `execute` as well, or use `SEND_ALLOW`, to rule them out.
+    pub(crate) fn allows_send(&self, cmd: &str) -> bool {
+        let cmd = normalize_command(cmd);
+        if self.send_deny.iter().any(|pattern| matches(pattern, &cmd)) {
+            return false;
+        }
+        self.send_allow.is_empty() || self.send_allow.iter().any(|pattern| matches(pattern, &cmd))
+    }
+}
+
+/// Lowercases the command and strips a leading `/` and the namespace (e.g. `minecraft:op` → `op`), so
+/// patterns can't be bypassed by writing the command differently.
+fn normalize_command(cmd: &str) -> String {
+    let cmd = cmd.trim().trim_start_matches('/').to_lowercase();
+    let (name, args) = cmd.split_once(' ').unwrap_or((&cmd, ""));
+    let name = name.rsplit(':').next().unwrap_or(name);
+    format!("{name} {}", args.trim()).trim_end().to_string()
+}
+
+/// Matches a pattern where `*` stands for any number of characters. A pattern without a wildcard also
+/// matches the command with any arguments, so `op` matches `op Notch`.
+fn matches(pattern: &str, cmd: &str) -> bool {
+    if !pattern.contains('*') {
+        return cmd == pattern
+            || cmd
+                .strip_prefix(pattern)
+                .is_some_and(|args| args.starts_with(' '));
+    }
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = cmd.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permissions(send_allow: &[&str], send_deny: &[&str]) -> Permissions {
+        let patterns =
+            |patterns: &[&str]| patterns.iter().map(|pattern| pattern.to_string()).collect();
+        Permissions::new(
+            Vec::new(),
+            Vec::new(),
+            HashMap::new(),
+            patterns(send_allow),
+            patterns(send_deny),
+        )
+    }
+
+    #[test]
+    fn normalizes_commands() {
+        assert_eq!(normalize_command("op Notch"), "op notch");
+        assert_eq!(normalize_command("/OP Notch"), "op notch");
+        assert_eq!(normalize_command("minecraft:op Notch"), "op notch");
+        assert_eq!(normalize_command("  /Minecraft:OP   Notch  "), "op notch");
+        assert_eq!(normalize_command("stop"), "stop");
+    }
+
+    #[test]
+    fn denies_commands_however_written() {
+        let permissions = permissions(&[], &["op", "/Deop"]);
+        for cmd in [
+            "op",
+            "op Notch",
+            "/OP Notch",
+            "minecraft:op Notch",
+            "/minecraft:deop Notch",
+        ] {
+            assert!(!permissions.allows_send(cmd), "{cmd}");
+        }
+        // Only whole command names match
+        assert!(permissions.allows_send("operator"));
+        assert!(permissions.allows_send("say op"));
+        // Nested commands aren't checked
+        assert!(permissions.allows_send("execute as @a run op Notch"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("whitelist *", "whitelist add notch"));
+        assert!(!matches("whitelist *", "whitelist"));
+        assert!(matches("gamerule * true", "gamerule keepinventory true"));
+        assert!(!matches("gamerule * true", "gamerule keepinventory false"));
+        assert!(matches("*", "anything"));
+        assert!(matches("time set *day", "time set midday"));
+        assert!(!matches("time set *day", "time set night"));
+    }
+
+    #[test]
+    fn allow_patterns_restrict_and_deny_wins() {
+        let permissions = permissions(&["say", "whitelist *"], &["whitelist remove *"]);
+        assert!(permissions.allows_send("say hello"));
+        assert!(permissions.allows_send("whitelist add Notch"));
+        assert!(!permissions.allows_send("whitelist remove Notch"));
+        assert!(!permissions.allows_send("op Notch"));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::config::ServerProfile;
+use crate::discord::Server;
+use crate::minecraft::ServerCommand;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use croner::{errors::CronError, Cron};
+use log::info;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// What a scheduled job does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Action {
+    Start,
+    Stop,
+    Restart,
+    Backup,
+    /// Announces the message in the ingame chat.
+    Say(String),
+    /// Sends the command to the server's console.
+    Command(String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Start => write!(f, "start"),
+            Action::Stop => write!(f, "stop"),
+            Action::Restart => write!(f, "restart"),
+            Action::Backup => write!(f, "backup"),
+            Action::Say(message) => write!(f, "say `{}`", message.replace('`', "'")),
+            Action::Command(command) => write!(f, "command `{}`", command.replace('`', "'")),
+        }
+    }
+}
+
+/// Runs an action whenever its cron expression matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Job {
+    pub(crate) name: String,
+    /// Cron expression with five fields, from minute to day of week.
+    pub(crate) cron: String,
+    pub(crate) action: Action,
+}
+
+impl Job {
+    pub(crate) fn new(name: String, cron: String, action: Action) -> Result<Self, CronError> {
+        parse_cron(&cron)?;
+        Ok(Self { name, cron, action })
+    }
+
+    /// The first time the job runs after `after`, `None` if the expression never matches.
+    pub(crate) fn next_run(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        parse_cron(&self.cron)
+            .ok()?
+            .find_next_occurrence(after, false)
+            .ok()
+    }
+}
+
+/// The next time any of the jobs runs after `after`, together with every job due at that time.
+pub(crate) fn next_due<'a>(
+    jobs: &'a [Job],
+    after: &DateTime<Tz>,
+) -> Option<(DateTime<Tz>, Vec<&'a Job>)> {
+    let runs: Vec<_> = jobs
+        .iter()
+        .filter_map(|job| Some((job.next_run(after)?, job)))
+        .collect();
+    let next = runs.iter().map(|(run, _)| *run).min()?;
+    let due = runs
+        .into_iter()
+        .filter(|(run, _)| *run == next)
+        .map(|(_, job)| job)
+        .collect();
+    Some((next, due))
+}
+
+fn parse_cron(expression: &str) -> Result<Cron, CronError> {
+    Cron::new(expression).parse()
+}
+
+/// Why a job didn't run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Skipped {
+    AlreadyRunning,
+    NotRunning,
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skipped::AlreadyRunning => write!(f, "the server is already running"),
+            Skipped::NotRunning => write!(f, "the server isn't running"),
+        }
+    }
+}
+
+/// Which jobs of a server are paused and when they last ran. Both are reset when EVE restarts.
+#[derive(Debug, Default)]
+pub(crate) struct JobStates {
+    paused: HashSet<String>,
+    last_runs: HashMap<String, LastRun>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LastRun {
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) result: Result<(), Skipped>,
+}
+
+impl JobStates {
+    pub(crate) fn paused(&self, job: &str) -> bool {
+        self.paused.contains(job)
+    }
+
+    /// Returns `false` if the job was already paused.
+    pub(crate) fn pause(&mut self, job: &str) -> bool {
+        self.paused.insert(job.to_string())
+    }
+
+    /// Returns `false` if the job wasn't paused.
+    pub(crate) fn resume(&mut self, job: &str) -> bool {
+        self.paused.remove(job)
+    }
+
+    pub(crate) fn last_run(&self, job: &str) -> Option<LastRun> {
+        self.last_runs.get(job).copied()
+    }
+}
+
+/// Runs the action of the job, unless the server isn't in the state the action needs.
+pub(crate) async fn run(
+    job: &Job,
+    server: &Server,
+    profile: &ServerProfile,
+) -> Result<(), Skipped> {
+    let running = server.manager.running().await;
+    let command = match &job.action {
+        Action::Start if running => Err(Skipped::AlreadyRunning),
+        Action::Start => Ok(ServerCommand::StartServer {
+            config: profile.server_config(),
+        }),
+        _ if !running => Err(Skipped::NotRunning),
+        Action::Stop => Ok(ServerCommand::Stop),
+        Action::Restart => Ok(ServerCommand::Restart {
+            countdown: profile.restart_countdown.clone(),
+        }),
+        Action::Backup => Ok(ServerCommand::Backup),
+        Action::Say(message) => Ok(ServerCommand::Stdin(format!("say {message}"))),
+        Action::Command(command) => Ok(ServerCommand::Stdin(command.clone())),
+    };
+
+    let result = match command {
+        Ok(command) => {
+            info!(
+                "Running job {} of Minecraft server {}",
+                job.name, server.name
+            );
+            server
+                .cmd_sender
+                .send(command)
+                .await
+                .expect("Failed sending value over sender");
+            Ok(())
+        }
+        Err(skipped) => {
+            info!(
+                "Skipped job {} of Minecraft server {}, {skipped}",
+                job.name, server.name
+            );
+            Err(skipped)
+        }
+    };
+    server.jobs.write().await.last_runs.insert(
+        job.name.clone(),
+        LastRun {
+            at: Utc::now(),
+            result,
+        },
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Berlin;
+
+    fn job(name: &str, cron: &str) -> Job {
+        Job::new(name.to_string(), cron.to_string(), Action::Backup).unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, min: u32) -> DateTime<Tz> {
+        Berlin
+            .with_ymd_and_hms(year, month, day, hour, min, 0)
+            .earliest()
+            .unwrap()
+    }
+
+    fn runs(job: &Job, after: DateTime<Tz>, count: usize) -> Vec<String> {
+        std::iter::successors(job.next_run(&after), |run| job.next_run(run))
+            .take(count)
+            .map(|run| run.to_rfc3339())
+            .collect()
+    }
+
+    #[test]
+    fn skipped_hour_runs_once_after_it() {
+        // On 2024-03-31 Berlin skips from 02:00 to 03:00
+        let job = job("nightly", "30 2 * * *");
+        assert_eq!(
+            runs(&job, at(2024, 3, 30, 12, 0), 3),
+            [
+                "2024-03-31T03:00:00+02:00",
+                "2024-04-01T02:30:00+02:00",
+                "2024-04-02T02:30:00+02:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_hour_runs_once() {
+        // On 2024-10-27 Berlin goes from 03:00 back to 02:00
+        let job = job("nightly", "30 2 * * *");
+        assert_eq!(
+            runs(&job, at(2024, 10, 26, 12, 0), 3),
+            [
+                "2024-10-27T02:30:00+02:00",
+                "2024-10-28T02:30:00+01:00",
+                "2024-10-29T02:30:00+01:00",
+            ]
+        );
+        let job = self::job("hourly", "0 * * * *");
+        assert_eq!(
+            runs(&job, at(2024, 10, 27, 1, 30), 3),
+            [
+                "2024-10-27T02:00:00+02:00",
+                "2024-10-27T03:00:00+01:00",
+                "2024-10-27T04:00:00+01:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn jobs_share_a_tick() {
+        let jobs = [
+            job("backup", "0 4 * * *"),
+            job("restart", "0 4 * * *"),
+            job("announce", "55 3 * * *"),
+            job("weekly", "0 4 * * 1"),
+        ];
+        let names = |due: Vec<&Job>| due.iter().map(|job| job.name.clone()).collect::<Vec<_>>();
+
+        let (next, due) = next_due(&jobs, &at(2024, 5, 5, 3, 0)).unwrap();
+        assert_eq!(next, at(2024, 5, 5, 3, 55));
+        assert_eq!(names(due), ["announce"]);
+
+        // The scheduler continues from the last run, so jobs due at the same time all run
+        let (next, due) = next_due(&jobs, &next).unwrap();
+        assert_eq!(next, at(2024, 5, 5, 4, 0));
+        assert_eq!(names(due), ["backup", "restart"]);
+
+        // 2024-05-06 is a Monday
+        let (next, due) = next_due(&jobs, &at(2024, 5, 6, 3, 56)).unwrap();
+        assert_eq!(next, at(2024, 5, 6, 4, 0));
+        assert_eq!(names(due), ["backup", "restart", "weekly"]);
+
+        assert!(next_due(&jobs, &next).is_some_and(|(run, _)| run > next));
+        assert_eq!(next_due(&[], &next), None);
+    }
+}